@@ -0,0 +1,72 @@
+// Timing harness shared by the benchmarks. Every measurement starts from the same seed, so the
+// numbers of two commits are comparable, and the results are written as a JSON report.
+#![allow(dead_code)]
+use std::fs;
+use std::path::PathBuf;
+use std::time::Instant;
+use biosim::simulation::random;
+use serde::Serialize;
+
+pub const SEED: u64 = 0x5eed;
+
+#[derive(Serialize)]
+pub struct Measurement {
+    pub scenario: String,
+    pub name: String,
+    pub repetitions: u32,
+    pub mean_ms: f64,
+    pub min_ms: f64,
+    pub max_ms: f64,
+}
+
+#[derive(Serialize)]
+pub struct Report {
+    pub bench: String,
+    pub seed: u64,
+    pub measurements: Vec<Measurement>,
+}
+
+impl Report {
+    pub fn new(bench: &str) -> Report {
+        Report { bench: bench.to_string(), seed: SEED, measurements: Vec::new() }
+    }
+
+    pub fn time<F: FnMut()>(&mut self, scenario: &str, name: &str, repetitions: u32, mut f: F) {
+        self.time_with_setup(scenario, name, repetitions, &mut (), |_| {}, |_| f());
+    }
+
+    // Runs `setup` before every repetition of `f`, out of the timed section.
+    pub fn time_with_setup<S, G, F>(&mut self, scenario: &str, name: &str, repetitions: u32, state: &mut S, mut setup: G, mut f: F)
+        where G: FnMut(&mut S), F: FnMut(&mut S) {
+        random::seed(SEED);
+        setup(state);
+        f(state);
+
+        let mut samples = Vec::with_capacity(repetitions as usize);
+        for _ in 0..repetitions {
+            setup(state);
+            let start = Instant::now();
+            f(state);
+            samples.push(start.elapsed().as_secs_f64() * 1000.0);
+        }
+        let measurement = Measurement {
+            scenario: scenario.to_string(),
+            name: name.to_string(),
+            repetitions,
+            mean_ms: samples.iter().sum::<f64>() / repetitions as f64,
+            min_ms: samples.iter().copied().fold(f64::INFINITY, f64::min),
+            max_ms: samples.iter().copied().fold(0.0, f64::max),
+        };
+        println!("{:<48} {:>12.3} ms (min {:.3})", format!("{} {}", scenario, name), measurement.mean_ms, measurement.min_ms);
+        self.measurements.push(measurement);
+    }
+
+    // Writes the report to target/bench-reports/<bench>.json and returns its path.
+    pub fn save(&self) -> PathBuf {
+        let directory = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target").join("bench-reports");
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join(format!("{}.json", self.bench));
+        fs::write(&path, serde_json::to_string_pretty(self).unwrap()).unwrap();
+        path
+    }
+}
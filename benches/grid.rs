@@ -0,0 +1,57 @@
+// Timings of the grid accesses and of the sensors that scan the grid.
+// Run with `cargo bench --bench grid`.
+mod common;
+
+use std::hint::black_box;
+use biosim::Parameters;
+use biosim::population::brain::sensor_actions::{ENABLED_SENSORS, Sensor};
+use biosim::population::genome::Genome;
+use biosim::simulation::peeps::Peeps;
+use biosim::simulation::random;
+use biosim::simulation::types::Coord;
+use common::{Report, SEED};
+
+const REPETITIONS: u32 = 20;
+
+// A crowded world like the default one, and a sparse one where most of the tiles are empty.
+const SCENARIOS: [(&str, &str); 2] = [
+    ("dense", "size_x: 256\nsize_y: 256\npopulation: 6000\nlong_probe_distance: 24"),
+    ("sparse", "size_x: 512\nsize_y: 512\npopulation: 2000\nlong_probe_distance: 24"),
+];
+
+fn sensor_index(sensor: Sensor) -> u16 {
+    ENABLED_SENSORS.iter().position(|s| *s == sensor).unwrap() as u16
+}
+
+fn main() {
+    let mut report = Report::new("grid");
+    for (scenario, yaml) in SCENARIOS {
+        let parameters: Parameters = serde_yaml::from_str(yaml).unwrap();
+        random::seed(SEED);
+        let peeps = Peeps::new(&parameters);
+        let genomes: Vec<Genome> = peeps.population.iter().skip(1).map(|i| i.genome.clone()).collect();
+
+        report.time(scenario, "grid scan", REPETITIONS, || {
+            let mut sum = 0u64;
+            for y in 0..parameters.size_y as i16 {
+                for x in 0..parameters.size_x as i16 {
+                    sum += peeps.world.at_coord(Coord(x, y)).is_occupied() as u64;
+                }
+            }
+            black_box(sum);
+        });
+
+        for sensor in [Sensor::Population, Sensor::PopulationFwd, Sensor::LongProbePopFwd, Sensor::LongProbeBarFwd, Sensor::BarrierFwd] {
+            let name = format!("sensor {}", sensor.get_name());
+            let index = sensor_index(sensor);
+            report.time(scenario, &name, REPETITIONS, || {
+                let mut sum = 0.0;
+                for individual in peeps.population.iter().skip(1) {
+                    sum += individual.get_sensor_value(index, &genomes, &peeps.world, &peeps.signals, &parameters, 0);
+                }
+                black_box(sum);
+            });
+        }
+    }
+    println!("Report written to {}", report.save().display());
+}
@@ -0,0 +1,103 @@
+// Timings of the simulation hot paths at several population and world sizes.
+// Run with `cargo bench --bench simulation`; the report is written to target/bench-reports.
+mod common;
+
+use std::hint::black_box;
+use rand::Rng;
+use biosim::Parameters;
+use biosim::population::brain::NeuralNet;
+use biosim::population::brain::sensor_actions::{ENABLED_SENSORS, Wiring};
+use biosim::population::genome::Genome;
+use biosim::simulation::peeps::{Parent, Peeps};
+use biosim::simulation::random;
+use common::{Report, SEED};
+
+const REPETITIONS: u32 = 20;
+// A whole generation takes long, so it's repeated fewer times
+const GENERATION_REPETITIONS: u32 = 3;
+// Generations run before timing, so the genomes went through selection and breeding
+const WARMUP_GENERATIONS: u32 = 2;
+
+const SCENARIOS: [(&str, &str); 3] = [
+    ("small", "size_x: 64\nsize_y: 64\npopulation: 300\nsteps_per_generation: 300"),
+    ("default", "size_x: 128\nsize_y: 128\npopulation: 600\nsteps_per_generation: 300"),
+    ("large", "size_x: 256\nsize_y: 256\npopulation: 6000\nsteps_per_generation: 300"),
+];
+
+fn genomes(peeps: &Peeps) -> Vec<Genome> {
+    peeps.population.iter().skip(1).map(|i| i.genome.clone()).collect()
+}
+
+fn run_generation(peeps: &mut Peeps, parameters: &Parameters, generation: u32) {
+    for step in 0..parameters.steps_per_generation as u32 {
+        peeps.simulate_all(parameters, step);
+    }
+    peeps.end_generation(generation);
+}
+
+fn bench_scenario(report: &mut Report, scenario: &str, parameters: &Parameters) {
+    random::seed(SEED);
+    let mut peeps = Peeps::new(parameters);
+    for generation in 0..WARMUP_GENERATIONS {
+        run_generation(&mut peeps, parameters, generation);
+    }
+    let genomes = genomes(&peeps);
+
+    let wiring = Wiring::from_parameters(parameters);
+    report.time(scenario, "NeuralNet::new", REPETITIONS, || {
+        for genome in genomes.iter() {
+            black_box(NeuralNet::new(genome, &[parameters.activation], parameters.max_number_neurons, wiring));
+        }
+    });
+
+    report.time(scenario, "feed_forward", REPETITIONS, || {
+        let world = &peeps.world;
+        let signals = &peeps.signals;
+        for individual in peeps.population.iter_mut().skip(1) {
+            black_box(individual.feed_forward(&genomes, world, signals, parameters, 0));
+        }
+    });
+
+    for (index, sensor) in ENABLED_SENSORS.iter().enumerate() {
+        let name = format!("sensor {}", sensor.get_name());
+        report.time(scenario, &name, REPETITIONS, || {
+            let mut sum = 0.0;
+            for individual in peeps.population.iter().skip(1) {
+                sum += individual.get_sensor_value(index as u16, &genomes, &peeps.world, &peeps.signals, parameters, 0);
+            }
+            black_box(sum);
+        });
+    }
+
+    // Everybody wants to move, as strongly as the actions can ask for it
+    report.time_with_setup(scenario, "drain_move_queue", REPETITIONS, &mut peeps, |peeps| {
+        let mut rng = random::rng();
+        for index in 1..=parameters.population {
+            Peeps::queue_for_move(&mut peeps.move_queue, index, (rng.gen_range(-2.0..2.0), rng.gen_range(-2.0..2.0)));
+        }
+    }, |peeps| peeps.drain_move_queue());
+
+    let parents: Vec<Parent> = peeps.population.iter().skip(1).map(Parent::from).collect();
+    report.time(scenario, "new_generation", REPETITIONS, || {
+        peeps.new_generation(&parents, WARMUP_GENERATIONS);
+    });
+
+    report.time(scenario, "end_generation", REPETITIONS, || {
+        black_box(peeps.end_generation(WARMUP_GENERATIONS));
+    });
+
+    let mut generation = WARMUP_GENERATIONS;
+    report.time(scenario, "generation", GENERATION_REPETITIONS, || {
+        run_generation(&mut peeps, parameters, generation);
+        generation += 1;
+    });
+}
+
+fn main() {
+    let mut report = Report::new("simulation");
+    for (scenario, yaml) in SCENARIOS {
+        let parameters: Parameters = serde_yaml::from_str(yaml).unwrap();
+        bench_scenario(&mut report, scenario, &parameters);
+    }
+    println!("Report written to {}", report.save().display());
+}
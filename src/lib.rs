@@ -0,0 +1,9 @@
+#![allow(dead_code)]
+// Explicit returns are the house style of this port
+#![allow(clippy::needless_return)]
+
+pub use crate::simulation::parameters::Parameters;
+
+pub mod simulation;
+pub mod population;
+pub mod render;
@@ -1,36 +1,46 @@
 #![allow(dead_code)]
+// Explicit returns are the house style of this port
+#![allow(clippy::needless_return)]
 
-use crate::simulation::parameters::Parameters;
-use crate::simulation::simulation::Simulation;
+use biosim::Parameters;
+use biosim::simulation::simulation::Simulation;
 
-mod simulation;
-mod population;
 mod util;
 
+use biosim::render::frame::Frame as ImageFrame;
+use biosim::render::recorder::{Recorder, write_gif};
+use biosim::simulation::replay::{Replay, ReplayWriter};
+use crate::util::TabsState;
 use crate::util::event::{Config, Event, Events};
+use std::path::{Path, PathBuf};
 use std::sync::RwLock;
 use std::{error::Error, io, time::Duration};
 use termion::{event::Key, input::MouseTerminal, raw::IntoRawMode, screen::AlternateScreen};
-use tui::widgets::Paragraph;
+use tui::widgets::{Paragraph, Tabs};
 use tui::{
+    backend::Backend,
     backend::TermionBackend,
-    layout::{Constraint, Direction, Layout},
-    style::Color,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Style},
+    symbols,
+    text::{Span, Spans},
     widgets::{
         canvas::{Canvas, Points},
-        Block, Borders,
+        Axis, Block, Borders, Chart, Dataset, GraphType,
     },
-    Terminal,
+    Frame, Terminal,
 };
 
 struct App<'a> {
     pub simulation: RwLock<Simulation<'a>>,
+    pub tabs: TabsState<'a>,
 }
 
 impl<'a> App<'a> {
     fn new(parameters: &'a Parameters) -> App<'a> {
         App {
-            simulation: RwLock::new(Simulation::initialize(parameters))
+            simulation: RwLock::new(Simulation::initialize(parameters)),
+            tabs: TabsState::new(vec!["World", "Charts"]),
         }
     }
 
@@ -39,7 +49,147 @@ impl<'a> App<'a> {
     }
 }
 
+// Runs the simulation without a terminal UI, printing a line per generation.
+fn run_headless(parameters: &Parameters) -> Result<(), Box<dyn Error>> {
+    let mut simulation = Simulation::initialize(parameters);
+    let mut recorder = Recorder::new(parameters);
+    let mut replay_writer = create_replay_writer(parameters)?;
+
+    while simulation.generation < parameters.max_generations {
+        let generation = simulation.generation;
+        run_recorded_step(&mut simulation, &mut recorder, &mut replay_writer)?;
+
+        if simulation.generation != generation {
+            for (number, island) in simulation.islands.iter().enumerate() {
+                let Some(statistics) = island.statistics.last() else {
+                    continue;
+                };
+                if simulation.islands.len() > 1 {
+                    print!("Island: {}, ", number);
+                }
+                print!("Generation: {}, Survivors: {}, Diversity: {:.3}, Mean genome length: {:.1}, Kills: {}",
+                       statistics.generation, statistics.survivors, statistics.diversity,
+                       statistics.mean_genome_length, statistics.kills);
+                if let Some(founder_lineages) = statistics.founder_lineages {
+                    print!(", Founder lineages: {}", founder_lineages);
+                }
+                if let Some(generations) = statistics.generations_to_common_ancestor {
+                    print!(", TMRCA: {}", generations);
+                }
+                if let (Some(births), Some(deaths_of_age), Some(starvations)) = (statistics.births, statistics.deaths_of_age, statistics.starvations) {
+                    print!(", Births: {}, Deaths of age: {}, Starvations: {}", births, deaths_of_age, starvations);
+                }
+                print!(", Mutations: {}", statistics.mutations);
+                if let Some(rates) = statistics.mean_mutation_rates {
+                    print!(", Mean mutation rates: point {:.2e}, insertion/deletion {:.2e}",
+                           rates.point, rates.insertion_deletion);
+                }
+                println!();
+            }
+        }
+    }
+    recorder.finish_generation(parameters)?;
+    if let Some(writer) = replay_writer.as_mut() {
+        writer.flush()?;
+    }
+    if parameters.track_lineage {
+        save_lineage(&simulation, parameters)?;
+    }
+
+    Ok(())
+}
+
+// Runs a step, after capturing the frame the recorder wants, and logs the replay events of the step.
+fn run_recorded_step(simulation: &mut Simulation, recorder: &mut Recorder, replay_writer: &mut Option<ReplayWriter>) -> Result<(), Box<dyn Error>> {
+    recorder.capture(simulation)?;
+    simulation.run_simulation_step();
+    if let Some(writer) = replay_writer.as_mut() {
+        writer.write_events(&simulation.islands[0].peeps.replay.take_events())?;
+    }
+    Ok(())
+}
+
+// Runs whole generations step by step, so that they are recorded like the others.
+fn run_recorded_generations(simulation: &mut Simulation, recorder: &mut Recorder, replay_writer: &mut Option<ReplayWriter>,
+                            generations: u32) -> Result<(), Box<dyn Error>> {
+    for _ in 0..generations {
+        let generation = simulation.generation;
+        while simulation.generation == generation {
+            run_recorded_step(simulation, recorder, replay_writer)?;
+        }
+    }
+    Ok(())
+}
+
+// Writes the ancestry of the living population as a Newick tree and all the parent records as JSON.
+// Each island has its own ids, so their lineages are saved apart.
+fn save_lineage(simulation: &Simulation, parameters: &Parameters) -> Result<(), Box<dyn Error>> {
+    let directory = PathBuf::from(&parameters.output_directory);
+    std::fs::create_dir_all(&directory)?;
+    for (number, island) in simulation.islands.iter().enumerate() {
+        let suffix = if simulation.islands.len() > 1 { format!("-{}", number) } else { String::new() };
+        let lineage = &island.peeps.lineage;
+        std::fs::write(directory.join(format!("lineage{}.nwk", suffix)), lineage.to_newick(&island.peeps.living_ids()))?;
+        std::fs::write(directory.join(format!("lineage{}.json", suffix)), lineage.to_json()?)?;
+    }
+    Ok(())
+}
+
+fn replay_path(parameters: &Parameters) -> PathBuf {
+    return PathBuf::from(&parameters.output_directory).join("replay.bin");
+}
+
+fn create_replay_writer(parameters: &Parameters) -> Result<Option<ReplayWriter>, Box<dyn Error>> {
+    if !parameters.save_replay {
+        return Ok(None);
+    }
+    let writer = ReplayWriter::create(&replay_path(parameters), parameters.size_x, parameters.size_y)?;
+    return Ok(Some(writer));
+}
+
+// Rebuilds a generation from a replay log and saves it as an animation next to the log.
+fn render_replay(path: &Path, generation: u32, parameters: &Parameters) -> Result<(), Box<dyn Error>> {
+    let replay = Replay::read_from_file(path)?;
+    if generation >= replay.generations() {
+        return Err(format!("The replay only contains {} generations", replay.generations()).into());
+    }
+    ImageFrame::check_size(replay.width, replay.height, parameters.display_scale)?;
+
+    let frames: Vec<ImageFrame> = replay.generation_steps(generation).iter()
+        .map(|locations| ImageFrame::render_locations(replay.width, replay.height, locations, parameters.display_scale))
+        .collect();
+    let output = path.with_file_name(format!("replay-gen-{:06}.gif", generation));
+    write_gif(&output, &frames)?;
+    println!("Wrote {} frames to {}", frames.len(), output.display());
+    Ok(())
+}
+
+fn argument_value<'a>(arguments: &'a [String], name: &str) -> Option<&'a String> {
+    let position = arguments.iter().position(|a| a == name)?;
+    return arguments.get(position + 1);
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
+    // Usage: biosim [parameters.yaml] [--headless] [--replay replay.bin --generation N]
+    let arguments: Vec<String> = std::env::args().skip(1).collect();
+    let headless = arguments.iter().any(|a| a == "--headless");
+    let parameters = match arguments.first().filter(|a| !a.starts_with("--")) {
+        Some(file_name) => Parameters::read_from_file(file_name)?,
+        None => Parameters::defaults(),
+    };
+
+    if let Some(replay_file) = argument_value(&arguments, "--replay") {
+        let generation = match argument_value(&arguments, "--generation") {
+            Some(generation) => generation.parse()?,
+            None => 0,
+        };
+        return render_replay(Path::new(replay_file), generation, &parameters);
+    }
+
+    if headless {
+        return run_headless(&parameters);
+    }
+
     // Terminal initialization
     let stdout = io::stdout().into_raw_mode()?;
     let stdout = MouseTerminal::from(stdout);
@@ -50,44 +200,35 @@ fn main() -> Result<(), Box<dyn Error>> {
     // Setup event handlers
     let config = Config {
         tick_rate: Duration::from_millis(250),
-        ..Default::default()
     };
     let events = Events::with_config(config);
 
-    let parameters = Parameters::defaults();
-
     // App
     let mut app = App::new(&parameters);
+    let mut recorder = Recorder::new(&parameters);
+    let mut replay_writer = create_replay_writer(&parameters)?;
 
     loop {
-        app.simulation.write().unwrap().run_simulation_step();
-        let locations = app.simulation.read().unwrap().peeps.get_population_locations();
-        let points = Points {
-            color: Color::Red,
-            coords: locations.as_slice(),
-        };
+        run_recorded_step(&mut app.simulation.write().unwrap(), &mut recorder, &mut replay_writer)?;
 
         terminal.draw(|f| {
             let chunks = Layout::default()
-            .direction(Direction::Vertical)
-            .margin(1)
-            .constraints(
-                [
-                    Constraint::Percentage(90),
-                    Constraint::Percentage(10),
-                ].as_ref()
-            )
-            .split(f.size());
-        let block = Canvas::default()
-            .paint(|ctx| {
-                ctx.draw(&points)
-            })
-            .x_bounds([0.0, parameters.size_x as f64])
-            .y_bounds([0.0, parameters.size_y as f64]);
-        f.render_widget(block, chunks[0]);
-        let block = Paragraph::new(format!("Step: {}, Generation: {}", app.simulation.read().unwrap().simulation_step % parameters.steps_per_generation as u32, app.simulation.read().unwrap().simulation_step / parameters.steps_per_generation as u32))
-        .block(Block::default().borders(Borders::ALL).title("Statistics"));
-        f.render_widget(block, chunks[1]);
+                .direction(Direction::Vertical)
+                .margin(1)
+                .constraints([Constraint::Length(3), Constraint::Min(0)].as_ref())
+                .split(f.size());
+            let titles = app.tabs.titles.iter().map(|t| Spans::from(*t)).collect();
+            let tabs = Tabs::new(titles)
+                .block(Block::default().borders(Borders::ALL).title("biosim"))
+                .highlight_style(Style::default().fg(Color::Yellow))
+                .select(app.tabs.index);
+            f.render_widget(tabs, chunks[0]);
+
+            let simulation = app.simulation.read().unwrap();
+            match app.tabs.index {
+                0 => draw_world_tab(f, &simulation, chunks[1]),
+                _ => draw_charts_tab(f, &simulation, chunks[1]),
+            }
         })?;
 
         match events.next()? {
@@ -99,15 +240,19 @@ fn main() -> Result<(), Box<dyn Error>> {
                 Key::Char('c') => {
                     let lock = app.simulation.write();
                     let mut sim = lock.unwrap();
-                    sim.run_simulation(100, parameters.steps_per_generation as u32);
+                    run_recorded_generations(&mut sim, &mut recorder, &mut replay_writer, 100)?;
                 }
 
                 Key::Char('s') => {
                     let lock = app.simulation.write();
                     let mut sim = lock.unwrap();
-                    sim.run_simulation(1, parameters.steps_per_generation as u32);
+                    run_recorded_generations(&mut sim, &mut recorder, &mut replay_writer, 1)?;
                 }
 
+                Key::Char('\t') | Key::Right => app.tabs.next(),
+
+                Key::Left => app.tabs.previous(),
+
                 _ => {}
             },
             Event::Tick => {
@@ -117,4 +262,93 @@ fn main() -> Result<(), Box<dyn Error>> {
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+// The tabs show the first island
+fn draw_world_tab<B: Backend>(f: &mut Frame<B>, simulation: &Simulation, area: Rect) {
+    let parameters = simulation.parameters;
+    let island = &simulation.islands[0];
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Percentage(90), Constraint::Percentage(10)].as_ref())
+        .split(area);
+
+    let locations = island.peeps.get_population_locations();
+    let points = Points {
+        color: Color::Red,
+        coords: locations.as_slice(),
+    };
+    let block = Canvas::default()
+        .paint(|ctx| {
+            ctx.draw(&points)
+        })
+        .x_bounds([0.0, parameters.size_x as f64])
+        .y_bounds([0.0, parameters.size_y as f64]);
+    f.render_widget(block, chunks[0]);
+
+    let mut statistics = format!("Step: {}, Generation: {}",
+                                 simulation.simulation_step % parameters.steps_per_generation as u32, simulation.generation);
+    if let Some(last) = island.statistics.last() {
+        statistics.push_str(&format!(", Survivors: {}, Diversity: {:.3}", last.survivors, last.diversity));
+    }
+    let block = Paragraph::new(statistics)
+        .block(Block::default().borders(Borders::ALL).title("Statistics"));
+    f.render_widget(block, chunks[1]);
+}
+
+fn draw_charts_tab<B: Backend>(f: &mut Frame<B>, simulation: &Simulation, area: Rect) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+        .split(area);
+    let top = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+        .split(rows[0]);
+    // Nobody can be killed unless kill_enabled is set, and the genomes only carry mutation rates
+    // with self_adaptive_mutation_rates, so those series are only charted then
+    let p = &simulation.parameters;
+    let bottom_charts = 1 + p.kill_enabled as u32 + p.self_adaptive_mutation_rates as u32;
+    let bottom = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(vec![Constraint::Ratio(1, bottom_charts); bottom_charts as usize])
+        .split(rows[1]);
+
+    let history = &simulation.islands[0].statistics;
+    draw_history_chart(f, "Survivors", &history.survivors(), Color::Green, top[0]);
+    draw_history_chart(f, "Genetic diversity", &history.diversity(), Color::Cyan, top[1]);
+    draw_history_chart(f, "Mean genome length", &history.mean_genome_length(), Color::Yellow, bottom[0]);
+    let mut next = 1;
+    if p.kill_enabled {
+        draw_history_chart(f, "Kills", &history.kills(), Color::Red, bottom[next]);
+        next += 1;
+    }
+    if p.self_adaptive_mutation_rates {
+        draw_history_chart(f, "Mean point mutation rate", &history.mean_point_mutation_rate(), Color::Magenta, bottom[next]);
+    }
+}
+
+// Draws a single (generation, value) series, scaling both axes to the recorded data.
+fn draw_history_chart<B: Backend>(f: &mut Frame<B>, title: &str, data: &[(f64, f64)], color: Color, area: Rect) {
+    let max_x = data.last().map(|p| p.0).unwrap_or(0.0).max(1.0);
+    // Rates are far below 1, so the y axis only falls back to 1 when there's nothing to scale to
+    let max_y = data.iter().map(|p| p.1).fold(0.0, f64::max);
+    let max_y = if max_y > 0.0 { max_y } else { 1.0 };
+    let max_y_label = if max_y < 0.01 { format!("{:.1e}", max_y) } else { format!("{:.2}", max_y) };
+
+    let datasets = vec![Dataset::default()
+        .marker(symbols::Marker::Braille)
+        .graph_type(GraphType::Line)
+        .style(Style::default().fg(color))
+        .data(data)];
+    let chart = Chart::new(datasets)
+        .block(Block::default().borders(Borders::ALL).title(Span::raw(title.to_string())))
+        .x_axis(Axis::default()
+            .title("Generation")
+            .bounds([0.0, max_x])
+            .labels(vec![Span::raw("0"), Span::raw(format!("{}", max_x))]))
+        .y_axis(Axis::default()
+            .bounds([0.0, max_y])
+            .labels(vec![Span::raw("0"), Span::raw(max_y_label)]));
+    f.render_widget(chart, area);
+}
@@ -1,21 +1,18 @@
+pub mod activation;
 pub mod sensor_actions;
 
-use std::cell::RefCell;
 use std::collections::HashMap;
-use crate::population::brain::sensor_actions::{ENABLED_ACTIONS, ENABLED_SENSORS};
-use crate::population::genome::{Genome, get_connection_map_from_genome, Node, remove_useless_neurons_from_genome, renumber_genome};
-use crate::population::genome::gene::{ACTION, NEURON, SENSOR};
+use crate::population::brain::activation::Activation;
+use crate::population::brain::sensor_actions::{ENABLED_ACTIONS, ENABLED_SENSORS, Wiring};
+use crate::population::genome::{get_connection_map_from_genome, Node, remove_useless_neurons_from_genome, renumber_genome};
+use crate::population::genome::gene::{ACTION, Gene, MAX_NODE_NUMBER, NEURON, SENSOR};
 
-pub struct Neuron {
-    pub output: f32,
-    pub driven: bool
-}
+// Output of every neuron until it's driven for the first time
+pub const INITIAL_NEURON_OUTPUT: f32 = 0.5;
 
-impl Neuron {
-    pub const fn initial_neuron_output() -> f32 {
-        return 0.5;
-    }
-}
+// Sensors and actions are numbered in the genes, so they must fit in a gene's node number
+const _: () = assert!(ENABLED_SENSORS.len() <= MAX_NODE_NUMBER as usize + 1);
+const _: () = assert!(ENABLED_ACTIONS.len() <= MAX_NODE_NUMBER as usize + 1);
 
 /// An individual's "brain" is a neural net specified by a set
 /// of Genes where each Gene specifies one connection in the neural net (see
@@ -27,8 +24,9 @@ impl Neuron {
 /// and sideways connection allowed. Weighted connections are allowed
 /// directly from any source to any action.
 ///
-/// Currently, the genome does not specify the activation function used in
-/// the neurons. (May be hardcoded to std::tanh() !!!)
+/// Each neuron has its own activation function, see [`Activation`]. Either a
+/// single one is chosen for the whole run, or the genome carries one for each
+/// neuron number.
 ///
 /// When the input is a sensor, the input value to the sink is the raw
 /// sensor value of type float and depends on the sensor. If the output
@@ -41,18 +39,36 @@ impl Neuron {
 /// by taking the 15-bit index modulo the max number of allowed neurons.
 /// In the neural net, the neurons that end up connected get new indices
 /// assigned sequentially starting at 0.
+///
+/// The connections are compiled into flat arrays with their source, sink and weight already decoded,
+/// so that evaluating the net doesn't touch the genes at all. Connections to neurons come first,
+/// then connections to actions.
 pub struct NeuralNet {
-    pub connections: Genome,
-    pub neurons: Vec<RefCell<Neuron>>,
+    pub connections: Vec<Gene>,
+    sources: Vec<u16>,
+    source_is_sensor: Vec<bool>,
+    sinks: Vec<u16>,
+    weights: Vec<f32>,
+    // Activation function of every neuron
+    pub activations: Vec<Activation>,
+    // Number of connections to neurons, i.e. index of the first connection to an action
+    neuron_connections: usize,
+    // Every sensor read by some connection, without repetitions
+    used_sensors: Vec<u16>,
+    pub neuron_outputs: Vec<f32>,
+    neuron_driven: Vec<bool>,
+    // Scratch buffer reused at every step
+    neuron_accumulators: Vec<f32>,
 }
 
 impl NeuralNet {
-    pub fn new(genome: &Genome, max_number_neurons: u16) -> NeuralNet {
-        let mut renumbered_genome = renumber_genome(&genome, max_number_neurons);
-        let mut connection_map: HashMap<u8, Node> = get_connection_map_from_genome(&renumbered_genome);
+    /// Neuron `n` of the genome, once renumbered, uses the activation `activations[n % activations.len()]`,
+    /// so a single activation applies to every neuron.
+    pub fn new(genome: &[Gene], activations: &[Activation], max_number_neurons: u16, wiring: Wiring) -> NeuralNet {
+        let mut renumbered_genome = renumber_genome(genome, max_number_neurons, wiring);
+        let mut connection_map: HashMap<u16, Node> = get_connection_map_from_genome(&renumbered_genome);
 
-        let mut neural_connections: Genome = vec![];
-        let mut neural_neurons: Vec<RefCell<Neuron>> = vec![];
+        let mut neural_connections: Vec<Gene> = vec![];
 
         remove_useless_neurons_from_genome(&mut renumbered_genome, &mut connection_map);
 
@@ -60,11 +76,9 @@ impl NeuralNet {
         // the number of outputs for each neuron. Now we'll renumber the connections
         // starting at zero.
         assert!(connection_map.len() <= max_number_neurons as usize);
-        let mut counter: u8 = 0;
-        for (_, value) in connection_map.iter_mut() {
+        for (counter, value) in (0u16..).zip(connection_map.values_mut()) {
             assert_ne!(value.outputs, 0);
             value.remapped_number = counter;
-            counter += 1;
         }
 
         // First, the connections from sensor or neuron to a neuron
@@ -79,6 +93,7 @@ impl NeuralNet {
                 }
             }
         }
+        let neuron_connections = neural_connections.len();
 
         // Last, the connections from sensor or neuron to an action
         for gene in renumbered_genome.iter() {
@@ -92,36 +107,102 @@ impl NeuralNet {
             }
         }
 
-        for key in connection_map.keys() {
-            neural_neurons.push(RefCell::new(Neuron {
-                output: Neuron::initial_neuron_output(),
-                driven: (connection_map.get(key).unwrap().other_inputs != 0)
-            }));
-        }
+        let neuron_driven: Vec<bool> = connection_map.values().map(|node| node.other_inputs != 0).collect();
+        // The map is iterated in the same order as when the neurons were numbered
+        let neuron_activations: Vec<Activation> = connection_map.keys()
+            .map(|neuron| activations[*neuron as usize % activations.len()])
+            .collect();
+        let mut used_sensors: Vec<u16> = neural_connections.iter()
+            .filter(|c| c.get_source_type() == SENSOR)
+            .map(|c| c.get_source_num())
+            .collect();
+        used_sensors.sort_unstable();
+        used_sensors.dedup();
 
         return NeuralNet {
+            sources: neural_connections.iter().map(|c| c.get_source_num()).collect(),
+            source_is_sensor: neural_connections.iter().map(|c| c.get_source_type() == SENSOR).collect(),
+            sinks: neural_connections.iter().map(|c| c.get_sink_num()).collect(),
+            weights: neural_connections.iter().map(|c| c.weight_as_float()).collect(),
+            activations: neuron_activations,
+            neuron_connections,
+            used_sensors,
+            neuron_outputs: vec![INITIAL_NEURON_OUTPUT; neuron_driven.len()],
+            neuron_accumulators: vec![0.0; neuron_driven.len()],
+            neuron_driven,
             connections: neural_connections,
-            neurons: neural_neurons
         }
     }
 
+    /// The sensors read by the net, in increasing order. The others don't need to be computed.
+    pub fn used_sensors(&self) -> &[u16] {
+        return &self.used_sensors;
+    }
+
+    /// Runs the net for one step and returns the level of every action, which is the sum of all its
+    /// weighted inputs and has an arbitrary range. `sensor_values` is indexed by sensor, and only
+    /// the entries of [`NeuralNet::used_sensors`] are read.
+    pub fn evaluate(&mut self, sensor_values: &[f32; ENABLED_SENSORS.len()]) -> [f32; ENABLED_ACTIONS.len()] {
+        let mut output = [0.0; ENABLED_ACTIONS.len()];
+        self.neuron_accumulators.fill(0.0);
+
+        // Connections are ordered so that all connections to neurons get processed before any
+        // connections to actions. Neurons read the outputs their sources latched in the previous step.
+        for i in 0..self.neuron_connections {
+            let input_value = self.input_value(i, sensor_values);
+            self.neuron_accumulators[self.sinks[i] as usize] += input_value * self.weights[i];
+        }
+
+        // Before the first connection to an action, latch the neuron outputs through their activation
+        // function, except for undriven neurons which act as bias feeds and don't change.
+        if self.neuron_connections == self.sources.len() {
+            return output;
+        }
+        for (neuron_index, driven) in self.neuron_driven.iter().enumerate() {
+            if *driven {
+                self.neuron_outputs[neuron_index] = self.activations[neuron_index].apply(self.neuron_accumulators[neuron_index]);
+            }
+        }
+
+        for i in self.neuron_connections..self.sources.len() {
+            let input_value = self.input_value(i, sensor_values);
+            output[self.sinks[i] as usize] += input_value * self.weights[i];
+        }
+
+        return output;
+    }
+
+    #[inline]
+    fn input_value(&self, connection: usize, sensor_values: &[f32; ENABLED_SENSORS.len()]) -> f32 {
+        let source = self.sources[connection] as usize;
+        if self.source_is_sensor[connection] {
+            return sensor_values[source];
+        }
+        return self.neuron_outputs[source];
+    }
+
+    // Neurons are labelled with their number and activation function, e.g. "N3(tanh)"
+    fn neuron_name(&self, neuron: u16) -> String {
+        return format!("N{}({})", neuron, self.activations[neuron as usize]);
+    }
+
     pub fn to_graph_string(&self) -> String {
         let mut graph_string = String::new();
         for connection in &self.connections {
             if connection.get_source_type() == SENSOR {
                 graph_string.push_str(&ENABLED_SENSORS[connection.get_source_num() as usize].to_string());
             } else {
-                graph_string.push_str(&format!("N{}", connection.get_source_num()));
+                graph_string.push_str(&self.neuron_name(connection.get_source_num()));
             }
 
-            graph_string.push_str(" ");
+            graph_string.push(' ');
 
             if connection.get_sink_type() == ACTION {
                 graph_string.push_str(&ENABLED_ACTIONS[connection.get_sink_num() as usize].to_string());
             } else {
-                graph_string.push_str(&format!("N{}", connection.get_sink_num()));
+                graph_string.push_str(&self.neuron_name(connection.get_sink_num()));
             }
-            graph_string.push_str("\n");
+            graph_string.push('\n');
         }
 
         return graph_string;
@@ -131,12 +212,12 @@ impl NeuralNet {
         let mut graph_string = String::new();
         graph_string.push_str("{\"");
         for connection in &self.connections {
-            if graph_string.len() > 2 {graph_string.push_str(",");
-            graph_string.push_str("\"");}
+            if graph_string.len() > 2 {graph_string.push(',');
+            graph_string.push('"');}
             if connection.get_source_type() == SENSOR {
                 graph_string.push_str(&ENABLED_SENSORS[connection.get_source_num() as usize].to_string());
             } else {
-                graph_string.push_str(&format!("N{}", connection.get_source_num()));
+                graph_string.push_str(&self.neuron_name(connection.get_source_num()));
             }
 
             graph_string.push_str("\"\\[DirectedEdge]\"");
@@ -144,11 +225,121 @@ impl NeuralNet {
             if connection.get_sink_type() == ACTION {
                 graph_string.push_str(&ENABLED_ACTIONS[connection.get_sink_num() as usize].to_string());
             } else {
-                graph_string.push_str(&format!("N{}", connection.get_sink_num()));
+                graph_string.push_str(&self.neuron_name(connection.get_sink_num()));
             }
-            graph_string.push_str("\"");
+            graph_string.push('"');
         }
-        graph_string.push_str("}");
+        graph_string.push('}');
         return graph_string;
     }
 }
+
+//<editor-fold desc="Unit tests">
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::population::genome::gene::Gene;
+    use crate::population::genome::make_random_genome;
+
+    // The evaluation as it was done before compiling the net: walking the genes and decoding them.
+    fn reference_evaluate(net: &NeuralNet, neuron_outputs: &mut [f32], sensor_value: &dyn Fn(u16) -> f32) -> [f32; ENABLED_ACTIONS.len()] {
+        let mut output = [0.0; ENABLED_ACTIONS.len()];
+        let mut neuron_accumulators = vec![0.0f32; neuron_outputs.len()];
+        let mut neuron_outputs_computed = false;
+        for gene in net.connections.iter() {
+            if gene.get_sink_type() == ACTION && !neuron_outputs_computed {
+                for (neuron_index, driven) in net.neuron_driven.iter().enumerate() {
+                    if *driven {
+                        neuron_outputs[neuron_index] = net.activations[neuron_index].apply(neuron_accumulators[neuron_index]);
+                    }
+                }
+                neuron_outputs_computed = true;
+            }
+
+            let input_value = if gene.get_source_type() == SENSOR {
+                sensor_value(gene.get_source_num())
+            } else {
+                neuron_outputs[gene.get_source_num() as usize]
+            };
+
+            if gene.get_sink_type() == ACTION {
+                output[gene.get_sink_num() as usize] += input_value * gene.weight_as_float();
+            } else {
+                neuron_accumulators[gene.get_sink_num() as usize] += input_value * gene.weight_as_float();
+            }
+        }
+        return output;
+    }
+
+    #[test]
+    fn test_evaluate_matches_gene_walk() {
+        for _ in 0..200 {
+            let genome = make_random_genome(24);
+            let activations: Vec<Activation> = (0..5).map(|_| Activation::random()).collect();
+            let mut net = NeuralNet::new(&genome, &activations, 5, Wiring::ALL);
+            let mut reference_outputs = net.neuron_outputs.clone();
+
+            for step in 0..4 {
+                let sensor_value = |sensor: u16| ((sensor as usize * 7 + step) % 11) as f32 / 10.0;
+                let mut sensor_values = [f32::NAN; ENABLED_SENSORS.len()];
+                for sensor in net.used_sensors() {
+                    sensor_values[*sensor as usize] = sensor_value(*sensor);
+                }
+
+                let expected = reference_evaluate(&net, &mut reference_outputs, &sensor_value);
+                let actual = net.evaluate(&sensor_values);
+                assert_eq!(actual.map(f32::to_bits), expected.map(f32::to_bits));
+                assert_eq!(net.neuron_outputs, reference_outputs);
+            }
+        }
+    }
+
+    #[test]
+    fn test_used_sensors() {
+        let genome = vec![
+            Gene::new(SENSOR, 3, ACTION, 0, 100),
+            Gene::new(SENSOR, 3, ACTION, 1, 100),
+            Gene::new(SENSOR, 1, NEURON, 0, 100),
+            Gene::new(NEURON, 0, ACTION, 2, 100),
+        ];
+        let net = NeuralNet::new(&genome, &[Activation::Tanh], 1, Wiring::ALL);
+        assert_eq!(net.used_sensors(), &[1, 3]);
+    }
+
+    #[test]
+    fn test_many_neurons() {
+        // Neurons beyond 127 keep their own numbers, instead of wrapping onto the low ones
+        let genome = vec![
+            Gene::new(SENSOR, 0, NEURON, 200, 100),
+            Gene::new(SENSOR, 1, NEURON, 72, 100),
+            Gene::new(NEURON, 200, NEURON, 72, 100),
+            Gene::new(NEURON, 72, ACTION, 0, 100),
+        ];
+        let net = NeuralNet::new(&genome, &[Activation::Tanh], 300, Wiring::ALL);
+        assert_eq!(net.neuron_outputs.len(), 2);
+        assert_eq!(net.connections.len(), 4);
+    }
+
+    #[test]
+    fn test_activations_per_neuron() {
+        let genome = vec![
+            Gene::new(SENSOR, 0, NEURON, 1, 8192),
+            Gene::new(NEURON, 1, NEURON, 2, 8192),
+            Gene::new(NEURON, 2, ACTION, 0, 8192),
+        ];
+        let activations = [Activation::Tanh, Activation::Step, Activation::Gaussian];
+        let mut net = NeuralNet::new(&genome, &activations, 3, Wiring::ALL);
+        let mut names: Vec<String> = (0..2).map(|neuron| net.neuron_name(neuron)).collect();
+        names.sort();
+        assert!(names == ["N0(gaussian)", "N1(step)"] || names == ["N0(step)", "N1(gaussian)"], "{:?}", names);
+        assert!(net.to_graph_string().contains("(step)"));
+
+        // The step neuron fires on a positive input, and the Gaussian one reads its latched output
+        let mut sensor_values = [0.0; ENABLED_SENSORS.len()];
+        sensor_values[0] = 0.5;
+        net.evaluate(&sensor_values);
+        let output = net.evaluate(&sensor_values);
+        assert_eq!(output[0], f32::exp(-1.0));
+    }
+}
+//</editor-fold>
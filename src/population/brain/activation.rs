@@ -0,0 +1,80 @@
+use std::fmt::{Display, Formatter};
+use rand::Rng;
+use serde::{Serialize, Deserialize};
+use crate::simulation::random;
+
+/// Activation function of a neuron, applied to the weighted sum of its inputs.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Activation {
+    // -1.0..1.0, as in biosim4
+    #[default]
+    Tanh = 0,
+    // 0.0..1.0
+    Sigmoid,
+    // 0.0..1.0, capped so that recurrent connections can't make the outputs grow without bound
+    Relu,
+    // 0.0 or 1.0
+    Step,
+    // 0.0..1.0, highest for an input of zero
+    Gaussian,
+}
+
+pub const ACTIVATIONS: [Activation; 5] = [Activation::Tanh, Activation::Sigmoid, Activation::Relu, Activation::Step, Activation::Gaussian];
+
+impl Activation {
+    #[inline]
+    pub fn apply(self, x: f32) -> f32 {
+        match self {
+            Activation::Tanh => f32::tanh(x),
+            Activation::Sigmoid => 1.0 / (1.0 + f32::exp(-x)),
+            Activation::Relu => x.clamp(0.0, 1.0),
+            Activation::Step => if x > 0.0 { 1.0 } else { 0.0 },
+            Activation::Gaussian => f32::exp(-x * x),
+        }
+    }
+
+    pub fn random() -> Activation {
+        return ACTIVATIONS[random::rng().gen_range(0..ACTIVATIONS.len())];
+    }
+
+    pub fn from_index(index: usize) -> Option<Activation> {
+        return ACTIVATIONS.get(index).copied();
+    }
+}
+
+impl Display for Activation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Activation::Tanh => "tanh",
+            Activation::Sigmoid => "sigmoid",
+            Activation::Relu => "relu",
+            Activation::Step => "step",
+            Activation::Gaussian => "gaussian",
+        };
+        return write!(f, "{}", name);
+    }
+}
+
+//<editor-fold desc="Unit tests">
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_activation_ranges() {
+        for activation in ACTIVATIONS {
+            for x in [-100.0, -1.0, -0.1, 0.0, 0.1, 1.0, 100.0] {
+                let y = activation.apply(x);
+                assert!((-1.0..=1.0).contains(&y), "{} of {} is {}", activation, x, y);
+            }
+            assert_eq!(Activation::from_index(activation as usize), Some(activation));
+        }
+        assert_eq!(Activation::Step.apply(0.0), 0.0);
+        assert_eq!(Activation::Gaussian.apply(0.0), 1.0);
+        assert_eq!(Activation::Sigmoid.apply(0.0), 0.5);
+        assert_eq!(Activation::Relu.apply(-2.0), 0.0);
+        assert_eq!(Activation::from_index(ACTIVATIONS.len()), None);
+    }
+}
+//</editor-fold>
@@ -1,6 +1,11 @@
 pub mod sensor_implementation;
 pub mod action_implementation;
 
+use serde::{Serialize, Deserialize};
+use crate::Parameters;
+use crate::simulation::food::FoodPattern;
+use crate::simulation::simulation::GenerationMode;
+
 //<editor-fold desc="Constants">
 const SENSOR_MIN: f32 = 0.0;
 const SENSOR_MAX: f32 = 1.0;
@@ -14,6 +19,8 @@ const ACTION_RANGE: f32 = ACTION_MAX - ACTION_MIN;
 //</editor-fold>
 
 //<editor-fold desc="Sensor implementation">
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
 pub enum Sensor {
     LocX = 0,             // I distance from left edge
     LocY,             // I distance from bottom
@@ -35,19 +42,31 @@ pub enum Sensor {
     Rnd,            //   random sensor value, uniform distribution
     Signal0,           // W strength of signal0 in neighborhood
     Signal0Fwd,       // W strength of signal0 in the forward-reverse axis
-    Signal0LR        // W strength of signal0 in the left-right axis
+    Signal0LR,        // W strength of signal0 in the left-right axis
+    FoodNearby,       // W food density in neighborhood
+    FoodFwd,          // W food density ahead
+    SpeciesFwd        // W another species ahead, closer is higher
 }
 
-pub const ENABLED_SENSORS: [Sensor; 21] =
+pub const ENABLED_SENSORS: [Sensor; 24] =
     [
         Sensor::LocX, Sensor::LocY, Sensor::BoundaryDistX, Sensor::BoundaryDist, Sensor::BoundaryDistY,
         Sensor::GeneticSimFwd, Sensor::LastMoveDirX, Sensor::LastMoveDirY, Sensor::LongProbePopFwd,
         Sensor::LongProbeBarFwd, Sensor::Population, Sensor::PopulationFwd, Sensor::PopulationLR,
         Sensor::Osc1, Sensor::Age, Sensor::BarrierFwd, Sensor::BarrierLR, Sensor::Rnd, Sensor::Signal0,
-        Sensor::Signal0Fwd, Sensor::Signal0LR
+        Sensor::Signal0Fwd, Sensor::Signal0LR, Sensor::FoodNearby, Sensor::FoodFwd, Sensor::SpeciesFwd
     ];
 
 impl Sensor {
+    /// Whether the parameters turn on the feature the sensor belongs to.
+    pub fn is_enabled(&self, p: &Parameters) -> bool {
+        match self {
+            Sensor::FoodNearby | Sensor::FoodFwd => p.food_pattern != FoodPattern::None,
+            Sensor::SpeciesFwd => !p.species.is_empty(),
+            _ => true,
+        }
+    }
+
     pub fn get_name(&self) -> String {
         match self {
             Sensor::LocX => { "loc X" }
@@ -71,13 +90,16 @@ impl Sensor {
             Sensor::Signal0 => { "signal 0" }
             Sensor::Signal0Fwd => { "signal 0 fwd" }
             Sensor::Signal0LR => { "signal 0 left-right" }
+            Sensor::FoodNearby => { "food nearby" }
+            Sensor::FoodFwd => { "food fwd" }
+            Sensor::SpeciesFwd => { "species fwd" }
         }.to_string()
     }
 }
 
-impl ToString for Sensor {
-    fn to_string(&self) -> String {
-        match self {
+impl std::fmt::Display for Sensor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let short_name = match self {
             Sensor::LocX => { "Lx" }
             Sensor::LocY => { "Ly" }
             Sensor::BoundaryDistX => { "EDx" }
@@ -99,7 +121,11 @@ impl ToString for Sensor {
             Sensor::Signal0 => { "Sg" }
             Sensor::Signal0Fwd => { "Sfd" }
             Sensor::Signal0LR => { "Slr" }
-        }.to_string()
+            Sensor::FoodNearby => { "Fd" }
+            Sensor::FoodFwd => { "Ffd" }
+            Sensor::SpeciesFwd => { "Spf" }
+        };
+        write!(f, "{}", short_name)
     }
 }
 //</editor-fold>
@@ -107,6 +133,8 @@ impl ToString for Sensor {
 //<editor-fold desc="Action Implementation">
 // I means the action affects the individual internally (Indiv)
 // W means the action also affects the environment (Peeps or Grid)
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
 pub enum Action {
     MoveX = 0,                   // W +- X component of movement
     MoveY,                   // W +- Y component of movement
@@ -124,18 +152,31 @@ pub enum Action {
     MoveLeft,                // W
     MoveRight,               // W
     MoveReverse,             // W
-    KillForward             // W
+    KillForward,             // W
+    Reproduce,               // W have a child nearby, in the continuous generation mode
+    Eat                      // W eat the food of the cell
 }
 
-pub const ENABLED_ACTIONS: [Action; 16] =
+pub const ENABLED_ACTIONS: [Action; 19] =
     [
         Action::MoveX, Action::MoveY, Action::MoveForward, Action::MoveRL, Action::MoveRandom,
         Action::SetOscillatorPeriod, Action::SetLongProbeDist, Action::SetResponsiveness,
         Action::EmitSignal0, Action::MoveEast, Action::MoveWest, Action::MoveNorth, Action::MoveSouth,
-        Action::MoveLeft, Action::MoveRight, Action::MoveReverse
+        Action::MoveLeft, Action::MoveRight, Action::MoveReverse, Action::KillForward,
+        Action::Reproduce, Action::Eat
     ];
 
 impl Action {
+    /// Whether the parameters turn on the feature the action belongs to.
+    pub fn is_enabled(&self, p: &Parameters) -> bool {
+        match self {
+            Action::KillForward => p.kill_enabled,
+            Action::Reproduce => p.generation_mode == GenerationMode::Continuous,
+            Action::Eat => p.food_pattern != FoodPattern::None,
+            _ => true,
+        }
+    }
+
     fn get_name(&self) -> String {
         match self {
             Action::MoveX => { "move X" }
@@ -155,13 +196,15 @@ impl Action {
             Action::MoveRight => { "move right" }
             Action::MoveReverse => { "move reverse" }
             Action::KillForward => { "kill forward" }
+            Action::Reproduce => { "reproduce" }
+            Action::Eat => { "eat" }
         }.to_string()
     }
 }
 
-impl ToString for Action {
-    fn to_string(&self) -> String {
-        match self {
+impl std::fmt::Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let short_name = match self {
             Action::MoveX => {"MvX"}
             Action::MoveY => {"MvY"}
             Action::MoveForward => {"MvF"}
@@ -179,7 +222,110 @@ impl ToString for Action {
             Action::MoveRight => {"MvR"}
             Action::MoveReverse => {"Mrv"}
             Action::KillForward => {"Klf"}
-        }.to_string()
+            Action::Reproduce => {"Rep"}
+            Action::Eat => {"Eat"}
+        };
+        write!(f, "{}", short_name)
     }
 }
-//</editor-fold>
\ No newline at end of file
+//</editor-fold>
+
+//<editor-fold desc="Wiring">
+/// The sensors and actions the genes of a run are wired to, as bit masks over the indices of
+/// [`ENABLED_SENSORS`] and [`ENABLED_ACTIONS`]. Those of the features a run doesn't use are left
+/// out, so they don't change how the genes of older genomes are wired.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Wiring {
+    sensors: u32,
+    actions: u32,
+}
+
+impl Wiring {
+    /// Every sensor and action.
+    pub const ALL: Wiring = Wiring {
+        sensors: u32::MAX >> (u32::BITS as usize - ENABLED_SENSORS.len()),
+        actions: u32::MAX >> (u32::BITS as usize - ENABLED_ACTIONS.len()),
+    };
+
+    pub fn from_parameters(p: &Parameters) -> Wiring {
+        let sensors = ENABLED_SENSORS.iter().enumerate()
+            .filter(|(_, sensor)| sensor.is_enabled(p))
+            .fold(0, |mask, (index, _)| mask | 1 << index);
+        let actions = ENABLED_ACTIONS.iter().enumerate()
+            .filter(|(_, action)| action.is_enabled(p))
+            .fold(0, |mask, (index, _)| mask | 1 << index);
+        return Wiring { sensors, actions };
+    }
+
+    /// Index in [`ENABLED_SENSORS`] of the sensor a gene's source number is wired to.
+    pub fn sensor(&self, number: u16) -> u16 {
+        return nth_set_bit(self.sensors, number);
+    }
+
+    /// Index in [`ENABLED_ACTIONS`] of the action a gene's sink number is wired to.
+    pub fn action(&self, number: u16) -> u16 {
+        return nth_set_bit(self.actions, number);
+    }
+
+    pub fn has_action(&self, index: usize) -> bool {
+        return self.actions & 1 << index != 0;
+    }
+}
+
+// The numbers wrap around the set bits, like they used to wrap around the whole tables
+fn nth_set_bit(mask: u32, number: u16) -> u16 {
+    let mut mask = mask;
+    for _ in 0..number % mask.count_ones() as u16 {
+        mask &= mask - 1;
+    }
+    return mask.trailing_zeros() as u16;
+}
+//</editor-fold>
+
+//<editor-fold desc="Unit tests">
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_wiring_skips_disabled_features() {
+        let mut p = Parameters::defaults();
+        p.food_pattern = FoodPattern::None;
+        let wiring = Wiring::from_parameters(&p);
+        // The genes are wired like before the food sensors and actions were added
+        let food_nearby = ENABLED_SENSORS.iter().position(|s| *s == Sensor::FoodNearby).unwrap() as u16;
+        assert!((0..100).all(|number| wiring.sensor(number) != food_nearby));
+        let eat = ENABLED_ACTIONS.iter().position(|a| *a == Action::Eat).unwrap();
+        assert!(!wiring.has_action(eat));
+        assert!(wiring.has_action(0));
+
+        p.food_pattern = FoodPattern::Uniform;
+        let wiring = Wiring::from_parameters(&p);
+        assert_eq!(wiring.sensor(food_nearby), food_nearby);
+        assert!(wiring.has_action(eat));
+        assert_eq!(Wiring::ALL.sensor(ENABLED_SENSORS.len() as u16), 0);
+    }
+
+    #[test]
+    fn test_default_wiring_is_the_original_one() {
+        // Without the optional features, the genes wrap around the 21 sensors and 16 actions they always had
+        let mut p = Parameters::defaults();
+        p.food_pattern = FoodPattern::None;
+        p.species.clear();
+        p.generation_mode = GenerationMode::Discrete;
+        let wiring = Wiring::from_parameters(&p);
+        for number in 0..100 {
+            assert_eq!(wiring.sensor(number), number % 21);
+            assert_eq!(wiring.action(number), number % 16);
+        }
+
+        p.generation_mode = GenerationMode::Continuous;
+        p.species = serde_yaml::from_str("[{ name: a, population: 1 }]").unwrap();
+        let wiring = Wiring::from_parameters(&p);
+        assert_eq!(ENABLED_SENSORS[wiring.sensor(21) as usize], Sensor::SpeciesFwd);
+        assert_eq!(ENABLED_ACTIONS[wiring.action(16) as usize], Action::Reproduce);
+        p.kill_enabled = true;
+        assert_eq!(ENABLED_ACTIONS[Wiring::from_parameters(&p).action(16) as usize], Action::KillForward);
+    }
+}
+//</editor-fold>
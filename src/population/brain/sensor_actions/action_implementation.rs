@@ -1,13 +1,19 @@
 #![allow(unused_variables)]
+// Every action takes all the queues, so that they share the signature of the dispatch table
+#![allow(clippy::too_many_arguments)]
 use crate::Parameters;
 use crate::population::brain::sensor_actions::Action;
+use crate::population::genome::traits::{LONG_PROBE_DISTANCE_RANGE, OSCILLATOR_PERIOD_RANGE, RESPONSIVENESS_RANGE};
 use crate::population::individual::Individual;
-use crate::simulation::peeps::{MoveQueue, Peeps, DeathQueue};
+use crate::simulation::peeps::Peeps;
+use crate::simulation::probability_to_bool;
+use crate::simulation::queues::{BirthQueue, DeathQueue, EatQueue, KillQueue, MoveQueue};
+use crate::simulation::simulation::GenerationMode;
 use crate::simulation::types::{Coord, Dir};
 
 // Gets the function corresponding to the given action, which accepts za
 // individual, a grid, and the input level.
-pub fn get_action_dispatch(action: &Action) -> fn(&mut Individual, &mut MoveQueue, &mut DeathQueue, &Parameters, f32) {
+pub fn get_action_dispatch(action: &Action) -> fn(&mut Individual, &mut MoveQueue, &mut DeathQueue, &mut BirthQueue, &mut EatQueue, &mut KillQueue, &Parameters, f32) {
     match action {
         Action::MoveX => move_x,
         Action::MoveY => move_y,
@@ -26,112 +32,121 @@ pub fn get_action_dispatch(action: &Action) -> fn(&mut Individual, &mut MoveQueu
         Action::MoveRight => move_right,
         Action::MoveReverse => move_reverse,
         Action::KillForward => kill_forward,
+        Action::Reproduce => reproduce,
+        Action::Eat => eat,
     }
 }
 
-fn move_x(individual: &mut Individual, move_queue: &mut MoveQueue, death_queue: &mut DeathQueue, p: &Parameters, level: f32) {
-    let individual = individual;
+fn move_x(individual: &mut Individual, move_queue: &mut MoveQueue, death_queue: &mut DeathQueue, birth_queue: &mut BirthQueue, eat_queue: &mut EatQueue, kill_queue: &mut KillQueue, p: &Parameters, level: f32) {
     Peeps::queue_for_move(move_queue,individual.index, (level, 0.0));
 }
 
-fn move_y(individual: &mut Individual, move_queue: &mut MoveQueue, death_queue: &mut DeathQueue, p: &Parameters, level: f32) {
-    let individual = individual;
+fn move_y(individual: &mut Individual, move_queue: &mut MoveQueue, death_queue: &mut DeathQueue, birth_queue: &mut BirthQueue, eat_queue: &mut EatQueue, kill_queue: &mut KillQueue, p: &Parameters, level: f32) {
     Peeps::queue_for_move(move_queue,individual.index, (0.0, level));
 }
 
-fn move_forward(individual: &mut Individual, move_queue: &mut MoveQueue, death_queue: &mut DeathQueue, p: &Parameters, level: f32) {
-    let individual = individual;
+fn move_forward(individual: &mut Individual, move_queue: &mut MoveQueue, death_queue: &mut DeathQueue, birth_queue: &mut BirthQueue, eat_queue: &mut EatQueue, kill_queue: &mut KillQueue, p: &Parameters, level: f32) {
 
     let last_move_offset: Coord = individual.last_move_direction.into();
     Peeps::queue_for_move(move_queue,individual.index, (last_move_offset.0 as f32 * level,
                                                    last_move_offset.1 as f32 *level));
 }
 
-fn move_rl(individual: &mut Individual, move_queue: &mut MoveQueue, death_queue: &mut DeathQueue, p: &Parameters, level: f32) {
-    let individual = individual;
+fn move_rl(individual: &mut Individual, move_queue: &mut MoveQueue, death_queue: &mut DeathQueue, birth_queue: &mut BirthQueue, eat_queue: &mut EatQueue, kill_queue: &mut KillQueue, p: &Parameters, level: f32) {
 
     let last_move_offset: Coord = individual.last_move_direction.rotate90deg_cw().into();
     Peeps::queue_for_move(move_queue,individual.index, (last_move_offset.0 as f32 * level,
                                                    last_move_offset.1 as f32 * -level));
 }
 
-fn move_random(individual: &mut Individual, move_queue: &mut MoveQueue, death_queue: &mut DeathQueue, p: &Parameters, level: f32) {
+fn move_random(individual: &mut Individual, move_queue: &mut MoveQueue, death_queue: &mut DeathQueue, birth_queue: &mut BirthQueue, eat_queue: &mut EatQueue, kill_queue: &mut KillQueue, p: &Parameters, level: f32) {
     let offset: Coord = Dir::random().into();
-    let individual = individual;
 
     Peeps::queue_for_move(move_queue,individual.index, (offset.0 as f32 * level, offset.1 as f32 * level));
 }
 
-fn set_oscillator_period(individual: &mut Individual, move_queue: &mut MoveQueue, death_queue: &mut DeathQueue, p: &Parameters, level: f32) {
-    let mut individual = individual;
+fn set_oscillator_period(individual: &mut Individual, move_queue: &mut MoveQueue, death_queue: &mut DeathQueue, birth_queue: &mut BirthQueue, eat_queue: &mut EatQueue, kill_queue: &mut KillQueue, p: &Parameters, level: f32) {
     let exponent = (f32::tanh(level) + 1.0)/2.0;
     let new_period = 1 + (1.5 + f32::exp(7.0 * exponent)) as u32;
-    individual.oscillation_period = new_period;
+    individual.oscillation_period = new_period.clamp(*OSCILLATOR_PERIOD_RANGE.start(), *OSCILLATOR_PERIOD_RANGE.end());
 }
 
-fn set_long_probe_distance(individual: &mut Individual, move_queue: &mut MoveQueue, death_queue: &mut DeathQueue, p: &Parameters, level: f32) {
-    let mut individual = individual;
+fn set_long_probe_distance(individual: &mut Individual, move_queue: &mut MoveQueue, death_queue: &mut DeathQueue, birth_queue: &mut BirthQueue, eat_queue: &mut EatQueue, kill_queue: &mut KillQueue, p: &Parameters, level: f32) {
     let normalized_level = (f32::tanh(level) + 1.0)/2.0;
-    individual.long_probe_distance += 1 + (normalized_level * p.long_probe_distance as f32) as u32;
+    let distance = individual.long_probe_distance + 1 + (normalized_level * p.long_probe_distance as f32) as u32;
+    individual.long_probe_distance = distance.clamp(*LONG_PROBE_DISTANCE_RANGE.start(), *LONG_PROBE_DISTANCE_RANGE.end());
 }
 
-fn set_responsiveness(individual: &mut Individual, move_queue: &mut MoveQueue, death_queue: &mut DeathQueue, p: &Parameters, level: f32) {
-    let mut individual = individual;
+fn set_responsiveness(individual: &mut Individual, move_queue: &mut MoveQueue, death_queue: &mut DeathQueue, birth_queue: &mut BirthQueue, eat_queue: &mut EatQueue, kill_queue: &mut KillQueue, p: &Parameters, level: f32) {
     let normalized_level = (f32::tanh(level) + 1.0)/2.0;
-    individual.responsiveness += normalized_level;
+    let responsiveness = individual.responsiveness + normalized_level;
+    individual.responsiveness = responsiveness.clamp(*RESPONSIVENESS_RANGE.start(), *RESPONSIVENESS_RANGE.end());
 }
 
 //TODO
-fn emit_signal0(individual: &mut Individual, move_queue: &mut MoveQueue, death_queue: &mut DeathQueue, p: &Parameters, level: f32) {}
+fn emit_signal0(individual: &mut Individual, move_queue: &mut MoveQueue, death_queue: &mut DeathQueue, birth_queue: &mut BirthQueue, eat_queue: &mut EatQueue, kill_queue: &mut KillQueue, p: &Parameters, level: f32) {}
 
-fn move_east(individual: &mut Individual, move_queue: &mut MoveQueue, death_queue: &mut DeathQueue, p: &Parameters, level: f32) {
-    let individual = individual;
+fn move_east(individual: &mut Individual, move_queue: &mut MoveQueue, death_queue: &mut DeathQueue, birth_queue: &mut BirthQueue, eat_queue: &mut EatQueue, kill_queue: &mut KillQueue, p: &Parameters, level: f32) {
 
     Peeps::queue_for_move(move_queue,individual.index, (level, 0.0));
 }
 
 
-fn move_west(individual: &mut Individual, move_queue: &mut MoveQueue, death_queue: &mut DeathQueue, p: &Parameters, level: f32) {
-    let individual = individual;
+fn move_west(individual: &mut Individual, move_queue: &mut MoveQueue, death_queue: &mut DeathQueue, birth_queue: &mut BirthQueue, eat_queue: &mut EatQueue, kill_queue: &mut KillQueue, p: &Parameters, level: f32) {
 
     Peeps::queue_for_move(move_queue,individual.index, (-level, 0.0));
 }
 
-fn move_north(individual: &mut Individual, move_queue: &mut MoveQueue, death_queue: &mut DeathQueue, p: &Parameters, level: f32) {
-    let individual = individual;
+fn move_north(individual: &mut Individual, move_queue: &mut MoveQueue, death_queue: &mut DeathQueue, birth_queue: &mut BirthQueue, eat_queue: &mut EatQueue, kill_queue: &mut KillQueue, p: &Parameters, level: f32) {
 
     Peeps::queue_for_move(move_queue,individual.index, (0.0, level));
 }
 
-fn move_south(individual: &mut Individual, move_queue: &mut MoveQueue, death_queue: &mut DeathQueue, p: &Parameters, level: f32) {
-    let individual = individual;
+fn move_south(individual: &mut Individual, move_queue: &mut MoveQueue, death_queue: &mut DeathQueue, birth_queue: &mut BirthQueue, eat_queue: &mut EatQueue, kill_queue: &mut KillQueue, p: &Parameters, level: f32) {
 
     Peeps::queue_for_move(move_queue,individual.index, (0.0, -level));
 }
 
-fn move_left(individual: &mut Individual, move_queue: &mut MoveQueue, death_queue: &mut DeathQueue, p: &Parameters, level: f32) {
-    let individual = individual;
+fn move_left(individual: &mut Individual, move_queue: &mut MoveQueue, death_queue: &mut DeathQueue, birth_queue: &mut BirthQueue, eat_queue: &mut EatQueue, kill_queue: &mut KillQueue, p: &Parameters, level: f32) {
 
     let last_move_offset: Coord = individual.last_move_direction.rotate90deg_ccw().into();
     Peeps::queue_for_move(move_queue,individual.index, (last_move_offset.0 as f32 * level,
                                                    last_move_offset.1 as f32 *level));
 }
 
-fn move_right(individual: &mut Individual, move_queue: &mut MoveQueue, death_queue: &mut DeathQueue, p: &Parameters, level: f32) {
-    let individual = individual;
+fn move_right(individual: &mut Individual, move_queue: &mut MoveQueue, death_queue: &mut DeathQueue, birth_queue: &mut BirthQueue, eat_queue: &mut EatQueue, kill_queue: &mut KillQueue, p: &Parameters, level: f32) {
 
     let last_move_offset: Coord = individual.last_move_direction.rotate90deg_cw().into();
     Peeps::queue_for_move(move_queue,individual.index, (last_move_offset.0 as f32 * level,
                                                    last_move_offset.1 as f32 *level));
 }
 
-fn move_reverse(individual: &mut Individual, move_queue: &mut MoveQueue, death_queue: &mut DeathQueue, p: &Parameters, level: f32) {
-    let individual = individual;
+fn move_reverse(individual: &mut Individual, move_queue: &mut MoveQueue, death_queue: &mut DeathQueue, birth_queue: &mut BirthQueue, eat_queue: &mut EatQueue, kill_queue: &mut KillQueue, p: &Parameters, level: f32) {
 
     let last_move_offset: Coord = individual.last_move_direction.into();
     Peeps::queue_for_move(move_queue,individual.index, (-last_move_offset.0 as f32 * level,
                                                    -last_move_offset.1 as f32 *level));
 }
 
-//TODO
-fn kill_forward(individual: &mut Individual, move_queue: &mut MoveQueue, death_queue: &mut DeathQueue, p: &Parameters, level: f32) {}
\ No newline at end of file
+// Only has an effect when `kill_enabled` is set. The victim in front of the individual is found
+// when the kill queue is drained.
+fn kill_forward(individual: &mut Individual, move_queue: &mut MoveQueue, death_queue: &mut DeathQueue, birth_queue: &mut BirthQueue, eat_queue: &mut EatQueue, kill_queue: &mut KillQueue, p: &Parameters, level: f32) {
+    if p.kill_enabled && probability_to_bool(f32::tanh(level)) {
+        kill_queue.push(individual.index);
+    }
+}
+
+// Only has an effect in the continuous generation mode. Whether the individual can afford a child
+// is checked when the birth queue is drained.
+fn reproduce(individual: &mut Individual, move_queue: &mut MoveQueue, death_queue: &mut DeathQueue, birth_queue: &mut BirthQueue, eat_queue: &mut EatQueue, kill_queue: &mut KillQueue, p: &Parameters, level: f32) {
+    if p.generation_mode == GenerationMode::Continuous && probability_to_bool(f32::tanh(level)) {
+        birth_queue.push(individual.index);
+    }
+}
+
+// How much is eaten, and the energy it gives, is settled when the eat queue is drained
+fn eat(individual: &mut Individual, move_queue: &mut MoveQueue, death_queue: &mut DeathQueue, birth_queue: &mut BirthQueue, eat_queue: &mut EatQueue, kill_queue: &mut KillQueue, p: &Parameters, level: f32) {
+    if probability_to_bool(f32::tanh(level)) {
+        eat_queue.push(individual.index);
+    }
+}
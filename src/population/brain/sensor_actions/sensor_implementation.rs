@@ -1,56 +1,72 @@
 #![allow(dead_code)]
 #![allow(unused_variables)]
+// The sensors share the signature of the dispatch table, which takes the genomes as a `&Vec`
+#![allow(clippy::ptr_arg)]
 
 use std::f32::consts::PI;
 
-use rand::{thread_rng, Rng};
+use rand::Rng;
 
 use crate::Parameters;
 use crate::population::genome::similarity::{genome_similarity, SimilarityMetric};
 use crate::population::brain::sensor_actions::Sensor;
 use crate::population::genome::Genome;
 use crate::population::individual::Individual;
+use crate::simulation::grid::{Neighborhood, NeighborhoodShape, Topology};
+use crate::simulation::random;
 use crate::simulation::signals::Signals;
 use crate::simulation::types::{Coord, Dir};
 use crate::simulation::world::World;
 
+// Counts the empty cells in front of the start location, up to `range`. Running into a barrier
+// or an edge of the world counts as seeing nobody, and returns `range`.
 fn long_probe_population_forward_sensor(start_location: Coord, direction: Dir, range: u32, world: &World) -> u32 {
-    let mut location = start_location;
+    let mut location = world.resolve(start_location + direction);
     let mut count = 0;
-    while count < range && world.is_in_bounds(location) && world.is_empty_at(location) {
-        location = location + direction;
-        count += 1;
+    while count < range {
+        match location {
+            Some(l) if world.is_empty_at(l) => {
+                location = world.resolve(l + direction);
+                count += 1;
+            }
+            _ => break,
+        }
     }
-    if !world.is_in_bounds(location) || world.is_barrier_at(location) {
-        return range;
-    } else {
-        return count;
+    match location {
+        Some(l) if !world.is_barrier_at(l) => count,
+        _ => range,
     }
 }
 
+// Counts the cells in front of the start location until a barrier is found, up to `range`.
+// The edges of a bounded world aren't barriers, so reaching one returns `range`.
 fn long_probe_barrier_forward_sensor(start_location: Coord, direction: Dir, range: u32, world: &World) -> u32 {
-    let mut location = start_location;
+    let mut location = world.resolve(start_location + direction);
     let mut count = 0;
-    while count < range && world.is_in_bounds(location) && !world.is_barrier_at(location) {
-        location = location + direction;
-        count += 1;
+    while count < range {
+        match location {
+            Some(l) if !world.is_barrier_at(l) => {
+                location = world.resolve(l + direction);
+                count += 1;
+            }
+            _ => break,
+        }
     }
-    if !world.is_in_bounds(location) {
-        return range;
-    } else {
-        return count;
+    match location {
+        Some(_) => count,
+        None => range,
     }
 }
 
-fn population_density(start_location: Coord, direction: Dir, range: u32, world: &World) -> f32 {
+fn population_density(start_location: Coord, direction: Dir, range: u32, shape: NeighborhoodShape, world: &World) -> f32 {
     let mut sum = 0.0;
-    world.apply_neighborhood_to_f(start_location, range as i16, |coord: Coord| {
-        if start_location != coord && world.is_occupied_at(coord) {
-            let offset = coord - start_location;
+    world.apply_to_occupants_in_neighborhood(start_location, Neighborhood::new(shape, range as i16), |coord: Coord| {
+        if start_location != coord {
+            let offset = world.displacement(start_location, coord);
             let angle = offset.ray_sameness_dir(direction);
             let distance = f32::sqrt((offset.0*offset.0 + offset.1*offset.1) as f32);
             let scaled = (1.0 / distance) * angle;
-            sum += scaled as f32;
+            sum += scaled;
         }
     });
     let max_sum = 6.0 * range as f32;
@@ -58,29 +74,31 @@ fn population_density(start_location: Coord, direction: Dir, range: u32, world:
     return (sensor_val + 1.0) / 2.0;
 }
 
-fn short_probe_barrier_distance(location: Coord, dir: Dir, range: u32, world: &World) -> f32 {
-    let mut count_forward = 0u32;
-    let mut current_location = location + dir;
-    while count_forward < range && world.is_in_bounds(current_location) && !world.is_barrier_at(current_location) {
-        count_forward += 1;
-        current_location = current_location + dir;
+// Number of free cells before a barrier, walking from the location along `dir`, up to `range`.
+fn barrier_distance_along(location: Coord, dir: Coord, range: u32, world: &World) -> u32 {
+    let mut count = 0u32;
+    let mut current_location = world.resolve(location + dir);
+    while count < range {
+        match current_location {
+            Some(l) if !world.is_barrier_at(l) => {
+                count += 1;
+                current_location = world.resolve(l + dir);
+            }
+            _ => break,
+        }
     }
-
-    if !world.is_in_bounds(current_location) {
-        count_forward = range;
+    if current_location.is_none() {
+        return range;
     }
+    return count;
+}
 
-    let mut count_backward = 0u32;
-    current_location = location - dir;
-    while count_backward < range && world.is_in_bounds(current_location) && !world.is_barrier_at(current_location) {
-        count_backward += 1;
-        current_location = current_location - dir;
-    }
-    if !world.is_in_bounds(current_location) {
-        count_backward = range;
-    }
+fn short_probe_barrier_distance(location: Coord, dir: Dir, range: u32, world: &World) -> f32 {
+    let offset: Coord = dir.into();
+    let count_forward = barrier_distance_along(location, offset, range, world);
+    let count_backward = barrier_distance_along(location, offset * -1, range, world);
 
-    let sensor_value = ((count_forward - count_backward) + range) as f32;
+    let sensor_value = (count_forward as i64 - count_backward as i64 + range as i64) as f32;
     return sensor_value / (2.0 * range as f32);
 }
 
@@ -107,6 +125,9 @@ pub fn get_sensor_dispatch(sensor: &Sensor) -> fn(&Individual, &Vec<Genome>, &Wo
         Sensor::Signal0 => signal,
         Sensor::Signal0Fwd => signal_fwd,
         Sensor::Signal0LR => signal_lr,
+        Sensor::FoodNearby => food_nearby,
+        Sensor::FoodFwd => food_fwd,
+        Sensor::SpeciesFwd => species_fwd,
     }
 }
 
@@ -118,38 +139,55 @@ fn loc_y(individual: &Individual, population_genomes: &Vec<Genome>,  world: &Wor
     (individual.location.1 / (world.height as i16 - 1)) as f32
 }
 
+// A torus has no boundaries, so the boundary distance sensors report the farthest possible
+// distance (1.0) everywhere.
 fn boundary_distance_x(individual: &Individual, population_genomes: &Vec<Genome>,  world: &World, signals: &Signals, p: &Parameters, simulation_step: u32) -> f32 {
-    let distance_x = i16::min(individual.location.0, (world.width as i16 - individual.location.0 - 1) as i16);
+    if world.topology == Topology::Torus {
+        return 1.0;
+    }
+    let distance_x = i16::min(individual.location.0, world.width as i16 - individual.location.0 - 1);
     return distance_x as f32/(world.width as f32 /2.0)
 }
 
 fn boundary_distance(individual: &Individual, population_genomes: &Vec<Genome>,  world: &World, signals: &Signals, p: &Parameters, simulation_step: u32) -> f32 {
-    let distance_x = i16::min(individual.location.0, (world.width as i16 - individual.location.0 - 1) as i16);
-    let distance_y = i16::min(individual.location.1, (world.height as i16 - individual.location.1 - 1) as i16);
+    if world.topology == Topology::Torus {
+        return 1.0;
+    }
+    let distance_x = i16::min(individual.location.0, world.width as i16 - individual.location.0 - 1);
+    let distance_y = i16::min(individual.location.1, world.height as i16 - individual.location.1 - 1);
     let closest_distance = i16::min(distance_x, distance_y);
     let max_possible = u16::max(world.width/2 - 1, world.height/2 - 1);
     return closest_distance as f32/max_possible as f32
 }
 
 fn boundary_distance_y(individual: &Individual, population_genomes: &Vec<Genome>,  world: &World, signals: &Signals, p: &Parameters, simulation_step: u32) -> f32 {
-    let distance_y = i16::min(individual.location.1, (world.height as i16 - individual.location.1 - 1) as i16);
+    if world.topology == Topology::Torus {
+        return 1.0;
+    }
+    let distance_y = i16::min(individual.location.1, world.height as i16 - individual.location.1 - 1);
     return distance_y as f32/(world.height as f32 /2.0)
 }
 
 fn genetic_similitude_fwd(individual: &Individual, population_genomes: &Vec<Genome>,  world: &World, signals: &Signals, p: &Parameters, simulation_step: u32) -> f32 {
-    let loc2 = individual.location + individual.last_move_direction;
-    if world.is_in_bounds(loc2) && world.is_occupied_at(loc2) {
-        let other_genome = population_genomes.get(world.at_coord(loc2) as usize);
-        match other_genome {
-            Some(other_genome) => {
-                return genome_similarity(&individual.genome , &other_genome, SimilarityMetric::JaroWinkler);
-            },
-            None => {
-                return 0.0;
-            }
+    let loc2 = match world.resolve(individual.location + individual.last_move_direction) {
+        Some(loc2) => loc2,
+        None => return 0.0,
+    };
+    match occupant_genome(loc2, population_genomes, world) {
+        Some(other_genome) => {
+            return genome_similarity(&individual.genome , other_genome, SimilarityMetric::JaroWinkler);
+        },
+        None => {
+            return 0.0;
         }
     }
-    return 0.0;
+}
+
+// Genome of the individual at the location, if there's one. The genomes start with the individual
+// at index 1, as the placeholder at index 0 is left out.
+fn occupant_genome<'g>(location: Coord, population_genomes: &'g [Genome], world: &World) -> Option<&'g Genome> {
+    let index = world.at_coord(location).occupant_index()?;
+    return population_genomes.get(index as usize - 1);
 }
 
 fn last_move_dir_x(individual: &Individual, population_genomes: &Vec<Genome>,  world: &World, signals: &Signals, p: &Parameters, simulation_step: u32) -> f32 {
@@ -174,33 +212,27 @@ fn last_move_dir_y(individual: &Individual, population_genomes: &Vec<Genome>,  w
 
 fn long_probe_population_fwd(individual: &Individual, population_genomes: &Vec<Genome>,  world: &World, signals: &Signals, p: &Parameters, simulation_step: u32) -> f32 {
     let direction = individual.last_move_direction;
-    return long_probe_population_forward_sensor(individual.location, direction, p.long_probe_distance, world) as f32;
+    return long_probe_population_forward_sensor(individual.location, direction, individual.long_probe_distance, world) as f32;
 }
 
 fn long_probe_barrier_fwd(individual: &Individual, population_genomes: &Vec<Genome>,  world: &World, signals: &Signals, p: &Parameters, simulation_step: u32) -> f32 {
     let direction = individual.last_move_direction;
-    return long_probe_barrier_forward_sensor(individual.location, direction, p.long_probe_distance, world) as f32;
+    return long_probe_barrier_forward_sensor(individual.location, direction, individual.long_probe_distance, world) as f32;
 }
 
 fn population(individual: &Individual, population_genomes: &Vec<Genome>,  world: &World, signals: &Signals, p: &Parameters, simulation_step: u32) -> f32 {
     let location = individual.location;
-    let mut occupied= 0;
-    let mut checked = 0;
-    world.apply_neighborhood_to_f(location, p.population_sensor_radius, |coord: Coord| {
-        checked += 1;
-        if world.is_occupied_at(coord) {
-            occupied += 1;
-        }
-    });
+    let neighborhood = Neighborhood::new(p.neighborhood_shape, p.population_sensor_radius);
+    let (occupied, checked) = world.count_occupants_in_neighborhood(location, neighborhood);
     return occupied as f32/checked as f32;
 }
 
 fn population_fwd(individual: &Individual, population_genomes: &Vec<Genome>,  world: &World, signals: &Signals, p: &Parameters, simulation_step: u32) -> f32 {
-    return population_density(individual.location, individual.last_move_direction, p.long_probe_distance, world);
+    return population_density(individual.location, individual.last_move_direction, p.long_probe_distance, p.neighborhood_shape, world);
 }
 
 fn population_lr(individual: &Individual, population_genomes: &Vec<Genome>,  world: &World, signals: &Signals, p: &Parameters, simulation_step: u32) -> f32 {
-    return population_density(individual.location, individual.last_move_direction.rotate90deg_cw(), p.long_probe_distance, world);
+    return population_density(individual.location, individual.last_move_direction.rotate90deg_cw(), p.long_probe_distance, p.neighborhood_shape, world);
 }
 
 fn oscillation(individual: &Individual, population_genomes: &Vec<Genome>,  world: &World, signals: &Signals, p: &Parameters, simulation_step: u32) -> f32 {
@@ -225,7 +257,7 @@ fn barrier_lr(individual: &Individual, population_genomes: &Vec<Genome>,  world:
 }
 
 fn random(individual: &Individual, population_genomes: &Vec<Genome>,  world: &World, signals: &Signals, p: &Parameters, simulation_step: u32) -> f32 {
-    return thread_rng().gen_range(0.0..=1.0);
+    return random::rng().gen_range(0.0..=1.0);
 }
 
 fn signal(individual: &Individual, population_genomes: &Vec<Genome>,  world: &World, signals: &Signals, p: &Parameters, simulation_step: u32) -> f32 {
@@ -238,4 +270,69 @@ fn signal_fwd(individual: &Individual, population_genomes: &Vec<Genome>,  world:
 
 fn signal_lr(individual: &Individual, population_genomes: &Vec<Genome>,  world: &World, signals: &Signals, p: &Parameters, simulation_step: u32) -> f32 {
     0.0
-}
\ No newline at end of file
+}
+
+fn food_nearby(individual: &Individual, population_genomes: &Vec<Genome>,  world: &World, signals: &Signals, p: &Parameters, simulation_step: u32) -> f32 {
+    let neighborhood = Neighborhood::new(p.neighborhood_shape, p.food_sensor_radius);
+    return world.food.density(individual.location, neighborhood);
+}
+
+fn food_fwd(individual: &Individual, population_genomes: &Vec<Genome>,  world: &World, signals: &Signals, p: &Parameters, simulation_step: u32) -> f32 {
+    return world.food.density_forward(individual.location, individual.last_move_direction, p.food_sensor_radius as u32);
+}
+
+// Looks ahead up to the long probe distance for the nearest individual. Returns 1.0 when it's next
+// to the individual and belongs to another species, down to almost 0.0 at the end of the range.
+// Individuals of the same species, or nobody, give 0.0.
+fn species_fwd(individual: &Individual, population_genomes: &Vec<Genome>,  world: &World, signals: &Signals, p: &Parameters, simulation_step: u32) -> f32 {
+    let direction = individual.last_move_direction;
+    let range = individual.long_probe_distance;
+    let mut location = individual.location;
+    for distance in 0..range {
+        location = match world.resolve(location + direction) {
+            Some(l) if !world.is_barrier_at(l) && l != individual.location => l,
+            _ => return 0.0,
+        };
+        if world.is_occupied_at(location) {
+            return match occupant_genome(location, population_genomes, world) {
+                Some(other) if other.species != individual.genome.species => 1.0 - distance as f32 / range as f32,
+                _ => 0.0,
+            };
+        }
+    }
+    return 0.0;
+}
+
+//<editor-fold desc="Unit tests">
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::population::genome::make_random_genome;
+    use crate::simulation::world::Cell;
+
+    #[test]
+    fn test_genetic_similarity_reads_the_neighbour() {
+        let p = Parameters::defaults();
+        let mut world = World::new(10, 10, Topology::Bounded);
+        let signals = Signals::new(1, 10, 10);
+        // Individuals 1 and 3 share a genome and face each other, individual 2 is elsewhere
+        let (shared, other) = (make_random_genome(8), make_random_genome(8));
+        let genomes = vec![shared.clone(), other.clone(), shared.clone()];
+        let mut individuals = [
+            Individual::new(1, 1, Coord(5, 5), shared.clone(), &p),
+            Individual::new(2, 2, Coord(1, 1), other, &p),
+            Individual::new(3, 3, Coord(6, 5), shared, &p),
+        ];
+        individuals[0].last_move_direction = Dir::from(Coord(1, 0));
+        individuals[2].last_move_direction = Dir::from(Coord(-1, 0));
+        for individual in individuals.iter() {
+            world.set_at_coord(individual.location, Cell::occupant(individual.index));
+        }
+
+        for individual in [&individuals[0], &individuals[2]] {
+            let similarity = genetic_similitude_fwd(individual, &genomes, &world, &signals, &p, 0);
+            assert_eq!(similarity, 1.0, "individual {} compared itself to the wrong genome", individual.index);
+        }
+    }
+}
+//</editor-fold>
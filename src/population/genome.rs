@@ -1,22 +1,79 @@
+pub mod crossover;
 pub mod gene;
 pub mod mutations;
 pub mod similarity;
+pub mod traits;
 
 use std::collections::HashMap;
+use std::error::Error;
+use std::ops::{Deref, DerefMut};
 use gene::Gene;
-use crate::population::brain::sensor_actions::{ENABLED_ACTIONS, ENABLED_SENSORS};
+use mutations::MutationRates;
+use traits::Traits;
+use crate::Parameters;
+use crate::population::brain::activation::Activation;
+use crate::population::brain::sensor_actions::Wiring;
 use crate::population::genome::gene::NEURON;
+use crate::population::species::SpeciesId;
 
 // An individual's genome is a set of Genes, see [`Gene`]. Each
 // gene is equivalent to one connection in a neural net. An individual's
-// neural net is derived from its set of genes
-pub type Genome = Vec<Gene>;
+// neural net is derived from its set of genes. The genome also carries the
+// individual's heritable baseline traits, see [`Traits`], and its own mutation
+// rates, see [`MutationRates`]. When `evolve_activations` is enabled, it also
+// carries the activation function of each neuron number. Its species, see
+// [`Species`](crate::population::species::Species), is inherited along with it.
+// It dereferences to its genes.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Genome {
+    pub genes: Vec<Gene>,
+    pub traits: Traits,
+    pub mutation_rates: MutationRates,
+    // Empty unless activations evolve
+    pub activations: Vec<Activation>,
+    pub species: SpeciesId,
+}
+
+impl Genome {
+    pub fn new(genes: Vec<Gene>, traits: Traits) -> Genome {
+        return Genome { genes, traits, mutation_rates: MutationRates::default(), activations: Vec::new(), species: 0 };
+    }
+}
+
+impl From<Vec<Gene>> for Genome {
+    fn from(genes: Vec<Gene>) -> Self {
+        return Genome::new(genes, Traits::default());
+    }
+}
+
+impl Deref for Genome {
+    type Target = Vec<Gene>;
+
+    fn deref(&self) -> &Self::Target {
+        return &self.genes;
+    }
+}
+
+impl<'a> IntoIterator for &'a Genome {
+    type Item = &'a Gene;
+    type IntoIter = std::slice::Iter<'a, Gene>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        return self.genes.iter();
+    }
+}
+
+impl DerefMut for Genome {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        return &mut self.genes;
+    }
+}
 
 pub fn genome_to_string(genome: &Genome) -> String {
     let mut string = String::new();
-    for gene in genome {
+    for gene in genome.iter() {
         string.push_str(&gene.to_string());
-        string.push_str(" ");
+        string.push(' ');
     }
     return string;
 }
@@ -26,45 +83,94 @@ pub fn empty_genome(length: usize) -> Genome {
     for _ in 0..length {
         genome.push(Gene::empty());
     }
-    return genome;
+    return Genome::from(genome);
 }
 
+// The genes, separated by ':', followed by '|' and the traits, then '|' and the bits of the
+// point and insertion/deletion mutation rates, then '|' and a digit for each activation, then '|'
+// and the species.
 pub fn genome_to_hex(genome: &Genome) -> String {
     let mut string = String::new();
-    for gene in genome {
+    for gene in genome.iter() {
         string.push_str(&gene.hex_string());
-        string.push_str(":");
+        string.push(':');
     }
+    string.push('|');
+    string.push_str(&genome.traits.hex_string());
+    string.push_str(&format!("|{:016x}{:016x}", genome.mutation_rates.point.to_bits(),
+                             genome.mutation_rates.insertion_deletion.to_bits()));
+    string.push('|');
+    for activation in &genome.activations {
+        string.push_str(&format!("{:x}", *activation as usize));
+    }
+    string.push_str(&format!("|{:x}", genome.species));
     return string;
 }
 
-// Returns by value a single genome with random genes.
+/// Parses the output of [`genome_to_hex`]. Genes written with the legacy 16-bit encoding are
+/// migrated, see [`Gene::from_hex`], and genomes saved without traits, mutation rates or species get
+/// the default ones.
+pub fn genome_from_hex(hex: &str) -> Result<Genome, Box<dyn Error>> {
+    let mut sections = hex.split('|');
+    let genes = sections.next().unwrap_or_default();
+    let traits = sections.next().map(Traits::from_hex).transpose()?.unwrap_or_default();
+    let mutation_rates = sections.next().map(mutation_rates_from_hex).transpose()?.unwrap_or_default();
+    let activations = sections.next().unwrap_or_default().chars()
+        .map(|digit| digit.to_digit(16).and_then(|index| Activation::from_index(index as usize))
+            .ok_or_else(|| format!("Invalid activation '{}'", digit)))
+        .collect::<Result<_, _>>()?;
+    let species = sections.next().map(|hex| SpeciesId::from_str_radix(hex, 16)).transpose()?.unwrap_or_default();
+    let genes = genes.split(':').filter(|gene| !gene.is_empty()).map(Gene::from_hex).collect::<Result<_, _>>()?;
+    return Ok(Genome { genes, traits, mutation_rates, activations, species });
+}
+
+fn mutation_rates_from_hex(hex: &str) -> Result<MutationRates, Box<dyn Error>> {
+    if hex.len() != 32 || !hex.is_ascii() {
+        return Err(format!("Invalid mutation rates '{}', expected 32 hex digits", hex).into());
+    }
+    let point = f64::from_bits(u64::from_str_radix(&hex[..16], 16)?);
+    let insertion_deletion = f64::from_bits(u64::from_str_radix(&hex[16..], 16)?);
+    return Ok(MutationRates { point, insertion_deletion });
+}
+
+// Returns by value a single genome with random genes and the default traits.
 pub fn make_random_genome(num_genes: usize) -> Genome {
     let mut genome = Vec::with_capacity(num_genes);
     for _ in 0..num_genes {
         genome.push(Gene::make_random_gene());
     }
+    return Genome::from(genome);
+}
+
+// A genome with random genes, and the traits set by the parameters, for individuals with no parents.
+pub fn make_founder_genome(num_genes: usize, p: &Parameters) -> Genome {
+    let mut genome = make_random_genome(num_genes);
+    genome.traits = Traits::from_parameters(p);
+    genome.mutation_rates = MutationRates::from_parameters(p);
+    if p.evolve_activations {
+        genome.activations = (0..p.max_number_neurons).map(|_| Activation::random()).collect();
+    }
     return genome;
 }
 
-// Renumbers the genome to the range 0..p.max_number_neurons so that the wiring can be made
-// to create the neural net.
-pub fn renumber_genome(genome: &Genome, max_number_neurons: u16) -> Genome {
+// Renumbers the genome to the range 0..p.max_number_neurons, and to the sensors and actions of
+// the wiring, so that the wiring can be made to create the neural net.
+pub fn renumber_genome(genome: &[Gene], max_number_neurons: u16, wiring: Wiring) -> Vec<Gene> {
     let mut new_genome = Vec::with_capacity(genome.len());
     for gene in genome.iter() {
-        let mut conn: Gene = gene.clone();
+        let mut conn: Gene = *gene;
 
         let new_source = if conn.get_source_type() == NEURON {
-            conn.get_source_num() % max_number_neurons as u8
+            conn.get_source_num() % max_number_neurons
         } else {
-            conn.get_source_num() % ENABLED_SENSORS.len() as u8
+            wiring.sensor(conn.get_source_num())
         };
         conn.set_source_num(new_source);
 
         let new_sink = if conn.get_sink_type() == NEURON {
-            conn.get_sink_num() % max_number_neurons as u8
+            conn.get_sink_num() % max_number_neurons
         } else {
-            conn.get_sink_num() % ENABLED_ACTIONS.len() as u8
+            wiring.action(conn.get_sink_num())
         };
         conn.set_sink_num(new_sink);
         new_genome.push(conn);
@@ -73,30 +179,29 @@ pub fn renumber_genome(genome: &Genome, max_number_neurons: u16) -> Genome {
     return new_genome;
 }
 
+// Connection counts can't overflow, as they are bounded by the length of the genome.
 pub struct Node {
-    pub remapped_number: u8,
-    pub outputs: u8,
-    pub self_inputs: u8,
-    pub other_inputs: u8,
+    pub remapped_number: u16,
+    pub outputs: usize,
+    pub self_inputs: usize,
+    pub other_inputs: usize,
 }
 
 
-pub fn get_connection_map_from_genome(genome: &Genome) -> HashMap<u8, Node> {
-    let mut connection_map: HashMap<u8, Node> = HashMap::new();
+pub fn get_connection_map_from_genome(genome: &[Gene]) -> HashMap<u16, Node> {
+    let mut connection_map: HashMap<u16, Node> = HashMap::new();
 
     for gene in genome.iter() {
         // If we dont find the key, then we create the node
         // Otherwise we increment the outputs, inputs and self_inputs as appropriate
         if gene.get_sink_type() == NEURON {
-            if !connection_map.contains_key(&gene.get_sink_num()) {
-                connection_map.insert(gene.get_sink_num(), Node {
+            connection_map.entry(gene.get_sink_num()).or_insert(Node {
                     remapped_number: 0,
                     outputs: 0,
                     self_inputs: 0,
                     other_inputs: 0,
                 });
-            }
-            let mut sink_connection = connection_map.get_mut(&gene.get_sink_num()).unwrap();
+            let sink_connection = connection_map.get_mut(&gene.get_sink_num()).unwrap();
 
             // Increase the number of inputs
             if gene.get_source_type() == NEURON && gene.get_source_num() == gene.get_sink_num() {
@@ -106,16 +211,14 @@ pub fn get_connection_map_from_genome(genome: &Genome) -> HashMap<u8, Node> {
             }
         }
         if gene.get_source_type() == NEURON {
-            if !connection_map.contains_key(&gene.get_source_num()) {
-                connection_map.insert(gene.get_source_num(),Node {
+            connection_map.entry(gene.get_source_num()).or_insert(Node {
                     remapped_number: 0,
                     outputs: 0,
                     self_inputs: 0,
                     other_inputs: 0,
                 });
-            }
 
-            let mut source_connection = connection_map.get_mut(&gene.get_source_num()).unwrap();
+            let source_connection = connection_map.get_mut(&gene.get_source_num()).unwrap();
             // Increase the number of outputs
             source_connection.outputs += 1;
         }
@@ -124,7 +227,7 @@ pub fn get_connection_map_from_genome(genome: &Genome) -> HashMap<u8, Node> {
     return connection_map;
 }
 
-fn remove_connections_to_neuron(genome: &mut Genome, connections: &mut HashMap<u8, Node>, neuron_num: u8) {
+fn remove_connections_to_neuron(genome: &mut Vec<Gene>, connections: &mut HashMap<u16, Node>, neuron_num: u16) {
     genome.retain(|gene| {
         if gene.get_sink_type() == NEURON && gene.get_sink_num() == neuron_num {
             if gene.get_source_type() == NEURON {
@@ -139,7 +242,7 @@ fn remove_connections_to_neuron(genome: &mut Genome, connections: &mut HashMap<u
     });
 }
 
-pub fn remove_useless_neurons_from_genome(genome: &mut Genome, connection_map: &mut HashMap<u8, Node>) {
+pub fn remove_useless_neurons_from_genome(genome: &mut Vec<Gene>, connection_map: &mut HashMap<u16, Node>) {
     let mut has_useless_neurons = true;
 
     while has_useless_neurons {
@@ -159,4 +262,46 @@ pub fn remove_useless_neurons_from_genome(genome: &mut Genome, connection_map: &
             connection_map.remove(&key);
         }
     }
-}
\ No newline at end of file
+}
+
+//<editor-fold desc="Unit tests">
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_genome_hex_round_trip() {
+        let mut genome = make_random_genome(10);
+        genome.traits = Traits::random();
+        genome.mutation_rates = MutationRates { point: 0.0123, insertion_deletion: 1e-5 };
+        genome.activations = vec![Activation::Gaussian, Activation::Tanh, Activation::Relu];
+        genome.species = 12;
+        assert_eq!(genome_from_hex(&genome_to_hex(&genome)).unwrap(), genome);
+
+        // Genomes saved with the legacy encoding keep their connections
+        let legacy = genome_from_hex("8583fff9:7f40000c:").unwrap();
+        assert_eq!(legacy.genes, vec![Gene::new(true, 5, true, 3, -7), Gene::new(false, 127, false, 64, 12)]);
+        assert_eq!(legacy.traits, Traits::default());
+        assert_eq!(legacy.mutation_rates, MutationRates::default());
+        assert!(legacy.activations.is_empty());
+        assert_eq!(legacy.species, 0);
+        assert!(genome_from_hex("8583fff9:|12").is_err());
+    }
+
+    #[test]
+    fn test_baseline_genome_migration() {
+        // The first genome_to_hex wrote the legacy genes without padding
+        let baseline_hex = |genes: &[(u16, i16)]| -> String {
+            return genes.iter().map(|(encoding, weight)| format!("{:x}{:x}:", encoding, weight)).collect();
+        };
+        let genes = [(0x8583, -7), (0x7f40, 0x1234), (0, 7), (0, -2), (0x8000, 1)];
+        let genome = genome_from_hex(&baseline_hex(&genes)).unwrap();
+        let expected: Vec<Gene> = genes.iter().map(|(encoding, weight)| Gene::from_legacy(*encoding, *weight)).collect();
+        assert_eq!(genome.genes, expected);
+
+        let error = genome_from_hex(&baseline_hex(&[(0x8583, -7), (0x7f40, 12)])).unwrap_err().to_string();
+        assert!(error.contains("'7f40c' can't be migrated"), "{}", error);
+        assert!(genome_from_hex(&format!("8583fff9:|{}|12", Traits::default().hex_string())).is_err());
+    }
+}
+//</editor-fold>
@@ -0,0 +1,173 @@
+use rand::Rng;
+use serde::{Serialize, Deserialize};
+use crate::population::brain::activation::Activation;
+use crate::population::genome::Genome;
+use crate::population::genome::gene::Gene;
+use crate::population::genome::mutations::MutationRates;
+use crate::population::genome::traits::Traits;
+use crate::simulation::random;
+
+/// How the genes of two parents are combined into the genome of their child. Genes are aligned by
+/// their position in the genome.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CrossoverStrategy {
+    // The head of the shorter parent, up to a random point, then the tail of the longer one
+    SinglePoint,
+    // The longer parent, with a random segment taken from the shorter one
+    TwoPoint,
+    // Each gene the parents share a position for comes from either of them, the rest from the longer one
+    Uniform,
+    // Like single point, but the child is as long as a random parent instead of always the longer one
+    LengthPreserving,
+}
+
+// The traits, mutation rates and activations of the child are combined separately, see
+// [`Traits::crossover`] and [`MutationRates::recombine`].
+pub fn crossover(parent_a: &Genome, parent_b: &Genome, strategy: CrossoverStrategy) -> Genome {
+    return Genome {
+        genes: crossover_genes(parent_a, parent_b, strategy),
+        traits: Traits::crossover(&parent_a.traits, &parent_b.traits),
+        mutation_rates: MutationRates::recombine(&parent_a.mutation_rates, &parent_b.mutation_rates),
+        activations: crossover_activations(&parent_a.activations, &parent_b.activations),
+        // Only individuals of the same species breed
+        species: parent_a.species,
+    };
+}
+
+// The activation of each neuron number comes from either parent
+fn crossover_activations(parent_a: &[Activation], parent_b: &[Activation]) -> Vec<Activation> {
+    let mut rng = random::rng();
+    let (longer, shorter) = if parent_a.len() > parent_b.len() { (parent_a, parent_b) } else { (parent_b, parent_a) };
+    let mut child = longer.to_vec();
+    for (activation, other) in child.iter_mut().zip(shorter.iter()) {
+        if rng.gen() {
+            *activation = *other;
+        }
+    }
+    return child;
+}
+
+fn crossover_genes(parent_a: &Genome, parent_b: &Genome, strategy: CrossoverStrategy) -> Vec<Gene> {
+    let mut rng = random::rng();
+    let (longer, shorter) = if parent_a.len() > parent_b.len() {
+        (parent_a, parent_b)
+    } else {
+        (parent_b, parent_a)
+    };
+
+    match strategy {
+        CrossoverStrategy::SinglePoint => {
+            let point = rng.gen_range(0..shorter.len());
+            let mut child = shorter[..point].to_vec();
+            child.extend_from_slice(&longer[point..]);
+            return child;
+        }
+        CrossoverStrategy::TwoPoint => {
+            let (first, second) = (rng.gen_range(0..=shorter.len()), rng.gen_range(0..=shorter.len()));
+            let (start, end) = (usize::min(first, second), usize::max(first, second));
+            let mut child = longer.to_vec();
+            child[start..end].copy_from_slice(&shorter[start..end]);
+            return child;
+        }
+        CrossoverStrategy::Uniform => {
+            let mut child = longer.to_vec();
+            for (gene, other) in child.iter_mut().zip(shorter.iter()) {
+                if rng.gen() {
+                    *gene = *other;
+                }
+            }
+            return child;
+        }
+        CrossoverStrategy::LengthPreserving => {
+            let (tail_parent, head_parent) = if rng.gen() { (parent_a, parent_b) } else { (parent_b, parent_a) };
+            let point = rng.gen_range(0..shorter.len());
+            let mut child = head_parent[..point].to_vec();
+            child.extend_from_slice(&tail_parent[point..]);
+            return child;
+        }
+    }
+}
+
+//<editor-fold desc="Unit tests">
+#[cfg(test)]
+mod test {
+    use crate::population::genome::gene::Gene;
+    use super::*;
+
+    // Genes of the first parent have weight 1, and genes of the second one weight 2
+    fn parents(length_a: usize, length_b: usize) -> (Genome, Genome) {
+        return (Genome::from(vec![Gene::new(true, 0, true, 0, 1); length_a]), Genome::from(vec![Gene::new(true, 0, true, 0, 2); length_b]));
+    }
+
+    fn genes_from_first(child: &Genome) -> usize {
+        return child.iter().filter(|gene| gene.weight == 1).count();
+    }
+
+    #[test]
+    fn test_uniform_inherits_half_of_the_shared_genes() {
+        random::seed(11);
+        let (a, b) = parents(20, 30);
+        let mut from_a = 0;
+        for _ in 0..500 {
+            let child = crossover(&a, &b, CrossoverStrategy::Uniform);
+            assert_eq!(child.len(), 30);
+            assert!(child[20..].iter().all(|gene| gene.weight == 2));
+            from_a += genes_from_first(&child);
+        }
+        let proportion = from_a as f32 / (500 * 20) as f32;
+        assert!((proportion - 0.5).abs() < 0.03, "proportion {}", proportion);
+    }
+
+    #[test]
+    fn test_two_point_takes_one_segment() {
+        random::seed(12);
+        let (a, b) = parents(20, 30);
+        let mut from_a = 0;
+        for _ in 0..500 {
+            let child = crossover(&a, &b, CrossoverStrategy::TwoPoint);
+            assert_eq!(child.len(), 30);
+            // The genes of the shorter parent are contiguous
+            let first = child.iter().position(|gene| gene.weight == 1);
+            let last = child.iter().rposition(|gene| gene.weight == 1);
+            if let (Some(first), Some(last)) = (first, last) {
+                assert!(last < 20);
+                assert_eq!(genes_from_first(&child), last - first + 1);
+            }
+            from_a += genes_from_first(&child);
+        }
+        // The segment covers about a third of the shared positions on average
+        let proportion = from_a as f32 / (500 * 20) as f32;
+        assert!((proportion - 1.0 / 3.0).abs() < 0.05, "proportion {}", proportion);
+    }
+
+    #[test]
+    fn test_single_point_keeps_the_longer_length() {
+        random::seed(7);
+        let (a, b) = parents(10, 16);
+        for _ in 0..100 {
+            let child = crossover(&a, &b, CrossoverStrategy::SinglePoint);
+            assert_eq!(child.len(), 16);
+            let point = genes_from_first(&child);
+            assert!(child[..point].iter().all(|gene| gene.weight == 1));
+        }
+    }
+
+    #[test]
+    fn test_length_preserving_takes_either_length() {
+        random::seed(7);
+        let (a, b) = parents(10, 16);
+        let mut as_long_as_a = 0;
+        for _ in 0..500 {
+            let child = crossover(&a, &b, CrossoverStrategy::LengthPreserving);
+            assert!(child.len() == 10 || child.len() == 16);
+            if child.len() == 10 {
+                as_long_as_a += 1;
+                // The tail comes from the parent that sets the length
+                assert_eq!(child.last().unwrap().weight, 1);
+            }
+        }
+        assert!((as_long_as_a as f32 / 500.0 - 0.5).abs() < 0.06, "{} of 500", as_long_as_a);
+    }
+}
+//</editor-fold>
@@ -1,8 +1,10 @@
+use std::error::Error;
 use rand::Rng;
+use crate::simulation::random;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Gene {
-    pub encoding: u16,
+    pub encoding: u32,
     pub weight: i16
 }
 
@@ -10,11 +12,25 @@ pub const SENSOR: bool = true;
 pub const ACTION: bool = true;
 pub const NEURON: bool = false;
 
+// Number of bits of a gene that carry information, for the encoding and the weight
+pub const GENE_BITS: u32 = u32::BITS + i16::BITS;
+// Source and sink numbers are 15-bit unsigned integers
+pub const MAX_NODE_NUMBER: u16 = 0x7fff;
+
+// Length of a gene in the hexadecimal format, and in the format of the legacy 16-bit encoding
+const HEX_DIGITS: usize = 12;
+const LEGACY_HEX_DIGITS: usize = 8;
+// Largest number of digits of each part of a legacy gene
+const LEGACY_PART_DIGITS: usize = 4;
+
 // This is quite more messy than the C++ version, as Rust doesn't have bitfields.
 /// Each gene specifies one synaptic connection in a neural net. Each connection has an input (source),
 /// which is either a sensor or another neuron, and an output (sink) which is either an action or another neuron.
 /// Each gene has a weight, which is a floating point value derived from a signed 16-bit integer.
 /// The signed integer weight is scaled to a small range, then cubed to provide fine resolution near zero.
+///
+/// The encoding used to be 16 bits wide, with 7-bit source and sink numbers. It is now 32 bits wide,
+/// with 15-bit numbers; [`Gene::from_legacy`] converts the old genes.
 impl Gene {
     //<editor-fold desc="Bitfield manipulation">
     pub fn weight_as_float(&self) -> f32 {
@@ -33,40 +49,42 @@ impl Gene {
         return self.encoding & (1 << bit) != 0;
     }
 
-    // Source type is in bit 16
+    // Source type is in bit 32
     pub fn get_source_type(&self) -> bool { // SENSOR or NEURON
-        return ((self.encoding & 0b1000000000000000) >> 15) == 1;
+        return (self.encoding >> 31) == 1;
     }
 
     pub fn set_source_type(&mut self, flag: bool) {
-        self.encoding = (self.encoding & !0b1000000000000000) | ((flag as u16) << 15);
+        self.encoding = (self.encoding & !(1 << 31)) | ((flag as u32) << 31);
     }
 
-    // Source num is in bit 15-9
-    pub fn get_source_num(&self) -> u8 {
-        return ((self.encoding & 0b0111111100000000) >> 8) as u8;
+    // Source num is in bits 31-17
+    pub fn get_source_num(&self) -> u16 {
+        return ((self.encoding >> 16) & MAX_NODE_NUMBER as u32) as u16;
     }
 
-    pub fn set_source_num(&mut self, num: u8) {
-        self.encoding = (self.encoding & !0b0111111100000000) | ((num as u16) << 8);
+    pub fn set_source_num(&mut self, num: u16) {
+        assert!(num <= MAX_NODE_NUMBER, "Source number {} doesn't fit in a gene", num);
+        self.encoding = (self.encoding & !((MAX_NODE_NUMBER as u32) << 16)) | ((num as u32) << 16);
     }
 
-    // Destination type is in bit 8
+    // Destination type is in bit 16
     pub fn get_sink_type(&self) -> bool { // NEURON or ACTION
-        return ((self.encoding & 0b0000000010000000) >> 7) == 1;
+        return ((self.encoding >> 15) & 1) == 1;
     }
 
     pub fn set_sink_type(&mut self, flag: bool) {
-        self.encoding = (self.encoding & !0b0000000010000000) | ((flag as u16) << 7);
+        self.encoding = (self.encoding & !(1 << 15)) | ((flag as u32) << 15);
     }
 
-    // Destination num is in bit 7-1
-    pub fn get_sink_num(&self) -> u8 {
-        return (self.encoding & 0b0000000001111111) as u8;
+    // Destination num is in bits 15-1
+    pub fn get_sink_num(&self) -> u16 {
+        return (self.encoding & MAX_NODE_NUMBER as u32) as u16;
     }
 
-    pub fn set_sink_num(&mut self, num: u8) {
-        self.encoding = (self.encoding & !0b0000000001111111) | (num as u16);
+    pub fn set_sink_num(&mut self, num: u16) {
+        assert!(num <= MAX_NODE_NUMBER, "Sink number {} doesn't fit in a gene", num);
+        self.encoding = (self.encoding & !(MAX_NODE_NUMBER as u32)) | (num as u32);
     }
     //</editor-fold>
 
@@ -77,17 +95,22 @@ impl Gene {
         };
     }
 
-    pub fn make_encoding(source_type: bool, source_num: u8, sink_type: bool, sink_num: u8) -> u16 {
-        return (source_type as u16) << 15 | (source_num as u16) << 8 | (sink_type as u16) << 7 | (sink_num as u16);
+    pub fn make_encoding(source_type: bool, source_num: u16, sink_type: bool, sink_num: u16) -> u32 {
+        let mut gene = Gene::empty();
+        gene.set_source_type(source_type);
+        gene.set_source_num(source_num);
+        gene.set_sink_type(sink_type);
+        gene.set_sink_num(sink_num);
+        return gene.encoding;
     }
 
     pub fn make_random_weight() -> i16 {
-        let mut rng = rand::thread_rng();
+        let mut rng = random::rng();
         return rng.gen()
     }
 
-    pub fn make_random_encoding() -> u16 {
-        let mut rng = rand::thread_rng();
+    pub fn make_random_encoding() -> u32 {
+        let mut rng = random::rng();
         return rng.gen()
     }
 
@@ -98,25 +121,78 @@ impl Gene {
         };
     }
 
-    pub fn new(source_type: bool, source_num: u8, sink_type: bool, sink_num: u8, weight: i16) -> Gene {
+    pub fn new(source_type: bool, source_num: u16, sink_type: bool, sink_num: u16, weight: i16) -> Gene {
         return Gene {
             encoding: Gene::make_encoding(source_type, source_num, sink_type, sink_num),
             weight
         };
     }
 
+    /// Converts a gene with the legacy 16-bit encoding: a source type bit, a 7-bit source number, a
+    /// sink type bit and a 7-bit sink number. The numbers are kept as they are, so the gene wires
+    /// the same connection as long as there are at most 128 neurons, sensors and actions.
+    pub fn from_legacy(encoding: u16, weight: i16) -> Gene {
+        return Gene::new(encoding >> 15 == 1, (encoding >> 8) & 0x7f, (encoding >> 7) & 1 == 1, encoding & 0x7f, weight);
+    }
+
     pub fn hex_string(&self) -> String {
-        return format!("{:x}{:x}", self.encoding, self.weight);
+        return format!("{:08x}{:04x}", self.encoding, self.weight);
+    }
+
+    /// Parses the output of [`Gene::hex_string`]. Genes of 8 digits or less are read as a legacy
+    /// 16-bit encoding followed by the weight, and migrated with [`Gene::from_legacy`].
+    ///
+    /// Legacy genes used to be written without padding, so that a short one, e.g. `7f40c`, may be
+    /// split in several ways. Those are only migrated when a single split leaves neither part with
+    /// leading zeros, and are an error otherwise.
+    pub fn from_hex(hex: &str) -> Result<Gene, Box<dyn Error>> {
+        if hex.is_empty() || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!("Gene '{}' isn't hexadecimal", hex).into());
+        }
+        match hex.len() {
+            HEX_DIGITS => {
+                let encoding = u32::from_str_radix(&hex[..8], 16)?;
+                let weight = u16::from_str_radix(&hex[8..], 16)? as i16;
+                return Ok(Gene { encoding, weight });
+            }
+            LEGACY_HEX_DIGITS => {
+                let encoding = u16::from_str_radix(&hex[..4], 16)?;
+                let weight = u16::from_str_radix(&hex[4..], 16)? as i16;
+                return Ok(Gene::from_legacy(encoding, weight));
+            }
+            2..LEGACY_HEX_DIGITS => return Gene::from_unpadded_legacy_hex(hex),
+            _ => return Err(format!("Gene '{}' should have {} hexadecimal digits, or at most {} for the legacy encoding",
+                                    hex, HEX_DIGITS, LEGACY_HEX_DIGITS).into()),
+        }
+    }
+
+    // The encoding and the weight were written with `{:x}`, which only writes a leading zero for 0
+    fn from_unpadded_legacy_hex(hex: &str) -> Result<Gene, Box<dyn Error>> {
+        let is_unpadded = |digits: &str| digits == "0" || !digits.starts_with('0');
+        let splits: Vec<usize> = (1..hex.len())
+            .filter(|&split| split <= LEGACY_PART_DIGITS && hex.len() - split <= LEGACY_PART_DIGITS)
+            .filter(|&split| is_unpadded(&hex[..split]) && is_unpadded(&hex[split..]))
+            .collect();
+        match splits[..] {
+            [split] => {
+                let encoding = u16::from_str_radix(&hex[..split], 16)?;
+                let weight = u16::from_str_radix(&hex[split..], 16)? as i16;
+                return Ok(Gene::from_legacy(encoding, weight));
+            }
+            [] => return Err(format!("Legacy gene '{}' can't be split into an encoding and a weight", hex).into()),
+            _ => return Err(format!("Unpadded legacy gene '{}' can't be migrated, it can be split into an encoding \
+                                     and a weight in {} ways", hex, splits.len()).into()),
+        }
     }
 
     pub fn bit_string(&self) -> String {
-        return format!("{:b}{:b}", self.encoding, self.weight);
+        return format!("{:032b}{:016b}", self.encoding, self.weight);
     }
 }
 
-impl ToString for Gene {
-    fn to_string(&self) -> String {
-        return format!("Gene(source_type={}, source_num={}, sink_type={}, sink_num={}, weight={})",
+impl std::fmt::Display for Gene {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return write!(f, "Gene(source_type={}, source_num={}, sink_type={}, sink_num={}, weight={})",
                        self.get_source_type(), self.get_source_num(), self.get_sink_type(), self.get_sink_num(), self.weight);
     }
 }
@@ -129,19 +205,64 @@ mod test {
     #[test]
     fn test_bit_field() {
         let mut gene = Gene::new(false, 16, false, 25, 1);
-        assert_eq!(gene.get_source_type(), false);
+        assert!(!gene.get_source_type());
         assert_eq!(gene.get_source_num(), 16);
-        assert_eq!(gene.get_sink_type(), false);
+        assert!(!gene.get_sink_type());
         assert_eq!(gene.get_sink_num(), 25);
 
         gene.set_sink_num(99);
         gene.set_source_num(35);
         gene.set_sink_type(false);
         gene.set_source_type(true);
-        assert_eq!(gene.get_source_type(), true);
+        assert!(gene.get_source_type());
         assert_eq!(gene.get_source_num(), 35);
-        assert_eq!(gene.get_sink_type(), false);
+        assert!(!gene.get_sink_type());
         assert_eq!(gene.get_sink_num(), 99);
+
+        gene.set_source_num(MAX_NODE_NUMBER);
+        gene.set_sink_num(300);
+        assert!(gene.get_source_type());
+        assert_eq!(gene.get_source_num(), MAX_NODE_NUMBER);
+        assert!(!gene.get_sink_type());
+        assert_eq!(gene.get_sink_num(), 300);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_number_out_of_range() {
+        Gene::empty().set_sink_num(MAX_NODE_NUMBER + 1);
+    }
+
+    #[test]
+    fn test_legacy_migration() {
+        // Sensor 5 to action 3 and neuron 127 to neuron 64, in the legacy encoding
+        let gene = Gene::from_legacy(0x8583, -7);
+        assert_eq!(gene, Gene::new(true, 5, true, 3, -7));
+        let gene = Gene::from_legacy(0x7f40, 12);
+        assert_eq!(gene, Gene::new(false, 127, false, 64, 12));
+
+        assert_eq!(Gene::from_hex("8583fff9").unwrap(), Gene::new(true, 5, true, 3, -7));
+    }
+
+    #[test]
+    fn test_unpadded_legacy_genes() {
+        // A zero encoding is a single digit, and the weight can't start with a zero
+        assert_eq!(Gene::from_hex("07").unwrap(), Gene::from_legacy(0, 7));
+        assert_eq!(Gene::from_hex("0fffe").unwrap(), Gene::from_legacy(0, -2));
+        assert_eq!(Gene::from_hex("80001").unwrap(), Gene::from_legacy(0x8000, 1));
+        // 7f40 and c, or 7f and 40c...
+        let error = Gene::from_hex("7f40c").unwrap_err().to_string();
+        assert!(error.contains("can't be migrated"), "{}", error);
+        assert!(Gene::from_hex("0").is_err());
+    }
+
+    #[test]
+    fn test_hex_round_trip() {
+        for gene in [Gene::new(true, 200, false, 4000, -1), Gene::new(false, 0, true, 0, 0), Gene::make_random_gene()] {
+            assert_eq!(Gene::from_hex(&gene.hex_string()).unwrap(), gene);
+        }
+        assert!(Gene::from_hex("123").is_err());
+        assert!(Gene::from_hex("zzzzzzzz").is_err());
     }
 }
 //</editor-fold>
\ No newline at end of file
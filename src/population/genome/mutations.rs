@@ -1,19 +1,89 @@
+use std::f32::consts::TAU;
 use rand::Rng;
 use crate::Parameters;
-use crate::population::genome::{Genome, empty_genome};
-use crate::population::genome::gene::Gene;
+use crate::population::brain::activation::Activation;
+use crate::population::genome::Genome;
+use crate::population::genome::crossover::crossover;
+use crate::population::genome::gene::{Gene, MAX_NODE_NUMBER};
+use crate::population::genome::traits::TRAITS;
+use crate::simulation::random;
+
+/// Number of times each mutation operator was applied, e.g. while breeding a generation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MutationCounts {
+    pub bit_flips: u32,
+    pub insertions: u32,
+    pub deletions: u32,
+    pub weight_perturbations: u32,
+    pub weight_bit_flips: u32,
+    pub rewirings: u32,
+    pub duplications: u32,
+    pub inversions: u32,
+    pub trait_bit_flips: u32,
+    pub activation_changes: u32,
+}
+
+impl std::fmt::Display for MutationCounts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return write!(f, "bit flips {}, insertions {}, deletions {}, weight perturbations {}, weight bit flips {}, rewirings {}, duplications {}, inversions {}, trait bit flips {}, activation changes {}",
+                      self.bit_flips, self.insertions, self.deletions, self.weight_perturbations,
+                      self.weight_bit_flips, self.rewirings, self.duplications, self.inversions, self.trait_bit_flips, self.activation_changes);
+    }
+}
+
+// Bounds of the evolved mutation rates, so that they can neither get stuck at zero nor exceed certainty
+pub const MIN_MUTATION_RATE: f64 = 1e-6;
+pub const MAX_MUTATION_RATE: f64 = 1.0;
+
+/// Mutation rates carried by each genome. They replace the global rates of the parameters when
+/// `self_adaptive_mutation_rates` is enabled, and evolve along with the genome.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MutationRates {
+    pub point: f64,
+    pub insertion_deletion: f64,
+}
+
+impl MutationRates {
+    pub fn from_parameters(p: &Parameters) -> MutationRates {
+        return MutationRates { point: p.point_mutation_rate, insertion_deletion: p.gene_insertion_deletion_rate };
+    }
+
+    /// Evolution strategy self-adaptation: each rate is multiplied by `exp(learning_rate * N(0, 1))`,
+    /// so that rates change on a logarithmic scale.
+    pub fn self_adapt(&mut self, learning_rate: f64) {
+        for rate in [&mut self.point, &mut self.insertion_deletion] {
+            let factor = f64::exp(learning_rate * gaussian(0.0, 1.0) as f64);
+            *rate = (*rate * factor).clamp(MIN_MUTATION_RATE, MAX_MUTATION_RATE);
+        }
+    }
+
+    // The geometric mean of the parents' rates, matching the logarithmic scale of the self-adaptation
+    pub fn recombine(parent_a: &MutationRates, parent_b: &MutationRates) -> MutationRates {
+        return MutationRates {
+            point: f64::sqrt(parent_a.point * parent_b.point),
+            insertion_deletion: f64::sqrt(parent_a.insertion_deletion * parent_b.insertion_deletion),
+        };
+    }
+}
+
+impl Default for MutationRates {
+    // Matches the defaults of the point mutation and insertion/deletion rate parameters
+    fn default() -> Self {
+        return MutationRates { point: 0.0001, insertion_deletion: 0.0001 };
+    }
+}
 
 pub fn random_bit_flip(genome: &mut Genome) {
-    let mut rng = rand::thread_rng();
+    let mut rng = random::rng();
     let element_index = rng.gen_range(0..genome.len());
-    let bit_index = rng.gen_range(0..16u8);
+    let bit_index = rng.gen_range(0..u32::BITS as u8);
     let bit = genome[element_index].get_bit(bit_index);
     genome[element_index].set_bit(bit_index, !bit);
 }
 
 pub fn crop_length(genome: &mut Genome, length: usize) {
     if genome.len() > length && length > 0 {
-        let truncate_back: bool = rand::random();
+        let truncate_back: bool = random::rng().gen();
         if truncate_back {
             genome.truncate(length);
         } else {
@@ -22,50 +92,271 @@ pub fn crop_length(genome: &mut Genome, length: usize) {
     }
 }
 
-pub fn random_insertion_deletion(genome: &mut Genome, p: &Parameters) {
-   let mut rng = rand::thread_rng();
-    if rng.gen_range(0.0..1.0) < p.gene_insertion_deletion_rate {
+// `rate` replaces the insertion/deletion rate of the parameters, see [`mutate`].
+pub fn random_insertion_deletion(genome: &mut Genome, p: &Parameters, rate: f64, counts: &mut MutationCounts) {
+   let mut rng = random::rng();
+    if rng.gen_range(0.0..1.0) < rate {
         if rng.gen_range(0.0..1.0) < p.delete_ration {
             if genome.len() > 1 {
                 let index = rng.gen_range(0..genome.len());
                 genome.remove(index);
+                counts.deletions += 1;
             }
         } else if genome.len() < p.max_genome_length {
             genome.push(Gene::make_random_gene());
+            counts.insertions += 1;
         }
     }
 }
 
-pub fn apply_point_mutation_to_genome(genome: &mut Genome, p: &Parameters) {
-    let mut rng = rand::thread_rng();
+pub fn apply_point_mutation_to_genome(genome: &mut Genome, rate: f64, counts: &mut MutationCounts) {
+    let mut rng = random::rng();
     for _ in 0..genome.len() {
-        if rng.gen_range(0.0..1.0) < p.point_mutation_rate {
+        if rng.gen_range(0.0..1.0) < rate {
             random_bit_flip(genome);
+            counts.bit_flips += 1;
         }
     }
 }
 
-pub fn breed_from_parents(parent_a: &Genome, parent_b: &Genome, p: &Parameters) -> Genome {
-    let mut rng = rand::thread_rng();
+/// Flips a random bit of each trait with the point mutation rate, as for the genes.
+pub fn apply_point_mutation_to_traits(genome: &mut Genome, rate: f64, counts: &mut MutationCounts) {
+    let mut rng = random::rng();
+    for t in TRAITS {
+        if rng.gen_range(0.0..1.0) < rate {
+            genome.traits.flip_bit(t, rng.gen_range(0..u16::BITS as u8));
+            counts.trait_bit_flips += 1;
+        }
+    }
+}
+
+/// Replaces the activation of each neuron number with a random one, with the given rate.
+pub fn apply_point_mutation_to_activations(genome: &mut Genome, rate: f64, counts: &mut MutationCounts) {
+    let mut rng = random::rng();
+    for activation in genome.activations.iter_mut() {
+        if rng.gen_range(0.0..1.0) < rate {
+            *activation = Activation::random();
+            counts.activation_changes += 1;
+        }
+    }
+}
+
+// Samples a normal distribution with the Box-Muller transform
+fn gaussian(mean: f32, stddev: f32) -> f32 {
+    let mut rng = random::rng();
+    let u1: f32 = 1.0 - rng.gen_range(0.0..1.0f32);
+    let u2: f32 = rng.gen_range(0.0..1.0f32);
+    return mean + stddev * f32::sqrt(-2.0 * f32::ln(u1)) * f32::cos(TAU * u2);
+}
 
-    let (biggest_parent, smallest_parent) = if parent_a.len() > parent_b.len() {
-        (parent_a, parent_b)
+/// Adds Gaussian noise to the float weight of the gene. The result saturates at the limits of the
+/// weight instead of wrapping around.
+pub fn perturb_weight(gene: &mut Gene, stddev: f32) {
+    let weight = gaussian(gene.weight_as_float(), stddev) * 8192.0;
+    gene.weight = weight.round().clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+}
+
+pub fn flip_weight_bit(gene: &mut Gene) {
+    let bit = random::rng().gen_range(0..i16::BITS);
+    gene.weight ^= 1 << bit;
+}
+
+/// Connects either the source or the sink of the gene to a random node, keeping the other end.
+pub fn rewire(gene: &mut Gene) {
+    let mut rng = random::rng();
+    let node_type: bool = rng.gen();
+    let node_num = rng.gen_range(0..=MAX_NODE_NUMBER);
+    if rng.gen() {
+        gene.set_source_type(node_type);
+        gene.set_source_num(node_num);
     } else {
-        (parent_b, parent_a)
-    };
+        gene.set_sink_type(node_type);
+        gene.set_sink_num(node_num);
+    }
+}
+
+/// Inserts a copy of a random gene right after it.
+pub fn duplicate_gene(genome: &mut Genome) {
+    let index = random::rng().gen_range(0..genome.len());
+    let gene = genome[index];
+    genome.insert(index + 1, gene);
+}
 
-    let mut child = empty_genome(biggest_parent.len());
-    let crossover_point = rng.gen_range(0..smallest_parent.len());
-    for i in 0..crossover_point {
-        child[i] = smallest_parent[i];
+/// Reverses the order of the genes of a random segment.
+pub fn invert_segment(genome: &mut Genome) {
+    let mut rng = random::rng();
+    let start = rng.gen_range(0..genome.len());
+    let end = rng.gen_range(start..genome.len());
+    genome[start..=end].reverse();
+}
+
+/// Applies every mutation operator to the genome, each with the rate set in the parameters scaled
+/// by the mutation rate trait of the genome, and adds the number of mutations of each kind to `counts`.
+/// With `self_adaptive_mutation_rates`, the rates carried by the genome are mutated first, and then
+/// used instead of the point mutation and insertion/deletion rates of the parameters.
+pub fn mutate(genome: &mut Genome, p: &Parameters, counts: &mut MutationCounts) {
+    let mut rng = random::rng();
+    let scale = genome.traits.mutation_rate() as f64;
+    let rates = if p.self_adaptive_mutation_rates {
+        genome.mutation_rates.self_adapt(p.mutation_rate_learning_rate);
+        genome.mutation_rates
+    } else {
+        MutationRates::from_parameters(p)
+    };
+    random_insertion_deletion(genome, p, rates.insertion_deletion * scale, counts);
+    if genome.len() < p.max_genome_length && rng.gen_range(0.0..1.0) < p.gene_duplication_rate * scale {
+        duplicate_gene(genome);
+        counts.duplications += 1;
+    }
+    if rng.gen_range(0.0..1.0) < p.segment_inversion_rate * scale {
+        invert_segment(genome);
+        counts.inversions += 1;
     }
 
-    for i in crossover_point..biggest_parent.len() {
-        child[i] = biggest_parent[i];
+    apply_point_mutation_to_genome(genome, rates.point * scale, counts);
+    for gene in genome.iter_mut() {
+        if rng.gen_range(0.0..1.0) < p.weight_perturbation_rate * scale {
+            perturb_weight(gene, p.weight_perturbation_stddev);
+            counts.weight_perturbations += 1;
+        }
+        if rng.gen_range(0.0..1.0) < p.weight_bit_flip_rate * scale {
+            flip_weight_bit(gene);
+            counts.weight_bit_flips += 1;
+        }
+        if rng.gen_range(0.0..1.0) < p.rewiring_rate * scale {
+            rewire(gene);
+            counts.rewirings += 1;
+        }
     }
+    apply_point_mutation_to_traits(genome, rates.point * scale, counts);
+    apply_point_mutation_to_activations(genome, rates.point * scale, counts);
+}
+
+pub fn breed_from_parents(parent_a: &Genome, parent_b: &Genome, p: &Parameters, counts: &mut MutationCounts) -> Genome {
+    let mut child = crossover(parent_a, parent_b, p.crossover);
 
     // apply random mutations
-    random_insertion_deletion(&mut child, p);
-    apply_point_mutation_to_genome(&mut child, p);
+    mutate(&mut child, p, counts);
     return child;
-}
\ No newline at end of file
+}
+
+//<editor-fold desc="Unit tests">
+#[cfg(test)]
+mod test {
+    use crate::population::genome::make_random_genome;
+    use crate::population::genome::traits::Traits;
+    use super::*;
+
+    #[test]
+    fn test_perturb_weight_saturates() {
+        let mut gene = Gene::new(true, 1, false, 2, i16::MAX - 1);
+        for _ in 0..100 {
+            perturb_weight(&mut gene, 10.0);
+        }
+        assert!(gene.weight_as_float().abs() <= 4.0);
+
+        let mut gene = Gene::new(true, 1, false, 2, 1000);
+        perturb_weight(&mut gene, 0.0);
+        assert_eq!(gene.weight, 1000);
+    }
+
+    #[test]
+    fn test_structural_operators() {
+        let genome = make_random_genome(12);
+        let sorted = |genome: &Genome| {
+            let mut genome = genome.clone();
+            genome.sort_by_key(|gene| (gene.encoding, gene.weight));
+            genome
+        };
+
+        let mut inverted = genome.clone();
+        invert_segment(&mut inverted);
+        assert_eq!(sorted(&inverted), sorted(&genome));
+
+        let mut duplicated = genome.clone();
+        duplicate_gene(&mut duplicated);
+        assert_eq!(duplicated.len(), 13);
+        assert!(duplicated.windows(2).any(|pair| pair[0] == pair[1]));
+
+        let mut gene = genome[0];
+        rewire(&mut gene);
+        assert_eq!(gene.weight, genome[0].weight);
+    }
+
+    #[test]
+    fn test_mutate_counts_operators() {
+        let mut p = Parameters::defaults();
+        p.point_mutation_rate = 0.0;
+        p.gene_insertion_deletion_rate = 0.0;
+        p.weight_perturbation_rate = 1.0;
+        p.weight_bit_flip_rate = 0.0;
+        p.rewiring_rate = 1.0;
+        p.gene_duplication_rate = 0.0;
+        p.segment_inversion_rate = 1.0;
+
+        let mut genome = make_random_genome(8);
+        let mut counts = MutationCounts::default();
+        mutate(&mut genome, &p, &mut counts);
+        assert_eq!(counts, MutationCounts { weight_perturbations: 8, rewirings: 8, inversions: 1, ..Default::default() });
+    }
+
+    #[test]
+    fn test_mutation_rate_trait_scales_rates() {
+        random::seed(5);
+        let mut p = Parameters::defaults();
+        p.point_mutation_rate = 0.1;
+        p.gene_insertion_deletion_rate = 0.0;
+
+        let flips = |mutation_rate: f32| {
+            let mut counts = MutationCounts::default();
+            for _ in 0..200 {
+                let mut genome = make_random_genome(10);
+                genome.traits = Traits::new(0.5, 34, 16, mutation_rate);
+                mutate(&mut genome, &p, &mut counts);
+            }
+            counts.bit_flips
+        };
+        let (low, high) = (flips(0.25), flips(4.0));
+        assert!(low < 100 && high > 600, "{} {}", low, high);
+
+        // Traits mutate too
+        p.point_mutation_rate = 1.0;
+        let mut genome = make_random_genome(1);
+        let traits = genome.traits;
+        let mut counts = MutationCounts::default();
+        mutate(&mut genome, &p, &mut counts);
+        assert_eq!(counts.trait_bit_flips, TRAITS.len() as u32);
+        assert_ne!(genome.traits, traits);
+    }
+
+    #[test]
+    fn test_self_adaptive_rates() {
+        random::seed(8);
+        let mut p = Parameters::defaults();
+        p.point_mutation_rate = 0.5;
+        let mut genome = make_random_genome(4);
+        genome.mutation_rates = MutationRates { point: 0.001, insertion_deletion: 0.001 };
+
+        // The rates of the genome are left alone unless the mode is enabled
+        mutate(&mut genome, &p, &mut MutationCounts::default());
+        assert_eq!(genome.mutation_rates, MutationRates { point: 0.001, insertion_deletion: 0.001 });
+
+        p.self_adaptive_mutation_rates = true;
+        mutate(&mut genome, &p, &mut MutationCounts::default());
+        assert_ne!(genome.mutation_rates.point, 0.001);
+        assert_ne!(genome.mutation_rates.insertion_deletion, 0.001);
+
+        // Rates stay in bounds however far they drift
+        p.mutation_rate_learning_rate = 10.0;
+        for _ in 0..100 {
+            mutate(&mut genome, &p, &mut MutationCounts::default());
+            assert!((MIN_MUTATION_RATE..=MAX_MUTATION_RATE).contains(&genome.mutation_rates.point));
+        }
+
+        let child = MutationRates::recombine(&MutationRates { point: 0.01, insertion_deletion: 1.0 },
+                                             &MutationRates { point: 0.0001, insertion_deletion: 0.01 });
+        assert!((child.point - 0.001).abs() < 1e-12);
+        assert!((child.insertion_deletion - 0.1).abs() < 1e-12);
+    }
+}
+//</editor-fold>
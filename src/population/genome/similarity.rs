@@ -1,7 +1,6 @@
-use std::mem::size_of;
 use strsim::generic_jaro_winkler;
 use crate::population::genome::Genome;
-use crate::population::genome::gene::Gene;
+use crate::population::genome::gene::GENE_BITS;
 
 pub enum SimilarityMetric {
     JaroWinkler,
@@ -31,9 +30,7 @@ fn genome_hamming_bits(genome1: &Genome, genome2: &Genome) -> f32 {
         panic!("Genomes must be of equal length");
     }
 
-    let bytes_per_element= size_of::<Gene>();
-    let total_bytes = genome1.len() * bytes_per_element;
-    let total_bits = total_bytes * 8;
+    let total_bits = genome1.len() * GENE_BITS as usize;
 
     let mut bit_difference = 0;
     for i in 0..genome1.len() {
@@ -0,0 +1,173 @@
+use std::error::Error;
+use std::ops::RangeInclusive;
+use rand::Rng;
+use crate::Parameters;
+use crate::simulation::random;
+
+/// Heritable baseline traits of an individual, kept in their own section of the genome. Each trait
+/// is stored as a raw 16-bit value that maps linearly onto the valid range of the trait.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trait {
+    Responsiveness,
+    OscillatorPeriod,
+    LongProbeDistance,
+    // Multiplier applied to every mutation rate when breeding the individual's children
+    MutationRate,
+}
+
+pub const TRAIT_COUNT: usize = 4;
+pub const TRAITS: [Trait; TRAIT_COUNT] = [Trait::Responsiveness, Trait::OscillatorPeriod, Trait::LongProbeDistance, Trait::MutationRate];
+
+pub const RESPONSIVENESS_RANGE: RangeInclusive<f32> = 0.0..=1.0;
+pub const OSCILLATOR_PERIOD_RANGE: RangeInclusive<u32> = 2..=2048;
+pub const LONG_PROBE_DISTANCE_RANGE: RangeInclusive<u32> = 1..=512;
+// The multiplier is stored as its base 2 logarithm, so that 1.0 sits in the middle of the range
+pub const MUTATION_RATE_LOG2_RANGE: RangeInclusive<f32> = -4.0..=4.0;
+
+// Oscillator period of the founders, as in biosim4
+pub const DEFAULT_OSCILLATOR_PERIOD: u32 = 34;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Traits {
+    pub values: [u16; TRAIT_COUNT],
+}
+
+// Maps a value of the range onto 0..=u16::MAX, clamping it first
+fn encode(value: f32, range: &RangeInclusive<f32>) -> u16 {
+    let fraction = (value.clamp(*range.start(), *range.end()) - range.start()) / (range.end() - range.start());
+    return (fraction * u16::MAX as f32).round() as u16;
+}
+
+fn decode(raw: u16, range: &RangeInclusive<f32>) -> f32 {
+    return range.start() + (raw as f32 / u16::MAX as f32) * (range.end() - range.start());
+}
+
+fn as_float_range(range: &RangeInclusive<u32>) -> RangeInclusive<f32> {
+    return *range.start() as f32..=*range.end() as f32;
+}
+
+impl Traits {
+    pub fn new(responsiveness: f32, oscillator_period: u32, long_probe_distance: u32, mutation_rate: f32) -> Traits {
+        return Traits {
+            values: [
+                encode(responsiveness, &RESPONSIVENESS_RANGE),
+                encode(oscillator_period as f32, &as_float_range(&OSCILLATOR_PERIOD_RANGE)),
+                encode(long_probe_distance as f32, &as_float_range(&LONG_PROBE_DISTANCE_RANGE)),
+                encode(mutation_rate.log2(), &MUTATION_RATE_LOG2_RANGE),
+            ]
+        };
+    }
+
+    /// The traits of the founders of a simulation, taken from the parameters.
+    pub fn from_parameters(p: &Parameters) -> Traits {
+        return Traits::new(p.responsiveness, DEFAULT_OSCILLATOR_PERIOD, p.long_probe_distance, 1.0);
+    }
+
+    pub fn random() -> Traits {
+        return Traits { values: random::rng().gen() };
+    }
+
+    pub fn get(&self, t: Trait) -> u16 {
+        return self.values[t as usize];
+    }
+
+    pub fn responsiveness(&self) -> f32 {
+        return decode(self.get(Trait::Responsiveness), &RESPONSIVENESS_RANGE);
+    }
+
+    pub fn oscillator_period(&self) -> u32 {
+        return decode(self.get(Trait::OscillatorPeriod), &as_float_range(&OSCILLATOR_PERIOD_RANGE)).round() as u32;
+    }
+
+    pub fn long_probe_distance(&self) -> u32 {
+        return decode(self.get(Trait::LongProbeDistance), &as_float_range(&LONG_PROBE_DISTANCE_RANGE)).round() as u32;
+    }
+
+    pub fn mutation_rate(&self) -> f32 {
+        return decode(self.get(Trait::MutationRate), &MUTATION_RATE_LOG2_RANGE).exp2();
+    }
+
+    pub fn flip_bit(&mut self, t: Trait, bit_index: u8) {
+        self.values[t as usize] ^= 1 << bit_index;
+    }
+
+    /// Each trait of the child comes from either parent.
+    pub fn crossover(parent_a: &Traits, parent_b: &Traits) -> Traits {
+        let mut rng = random::rng();
+        let mut child = *parent_a;
+        for (value, other) in child.values.iter_mut().zip(parent_b.values.iter()) {
+            if rng.gen() {
+                *value = *other;
+            }
+        }
+        return child;
+    }
+
+    pub fn hex_string(&self) -> String {
+        return self.values.iter().map(|value| format!("{:04x}", value)).collect();
+    }
+
+    pub fn from_hex(hex: &str) -> Result<Traits, Box<dyn Error>> {
+        if hex.len() != 4 * TRAIT_COUNT || !hex.is_ascii() {
+            return Err(format!("Invalid traits '{}', expected {} hex digits", hex, 4 * TRAIT_COUNT).into());
+        }
+        let mut values = [0; TRAIT_COUNT];
+        for (i, value) in values.iter_mut().enumerate() {
+            *value = u16::from_str_radix(&hex[4 * i..4 * (i + 1)], 16)?;
+        }
+        return Ok(Traits { values });
+    }
+}
+
+impl Default for Traits {
+    // Matches the defaults of the responsiveness and long probe distance parameters
+    fn default() -> Self {
+        return Traits::new(0.5, DEFAULT_OSCILLATOR_PERIOD, 16, 1.0);
+    }
+}
+
+//<editor-fold desc="Unit tests">
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_traits_round_trip() {
+        let traits = Traits::new(0.25, 100, 40, 2.0);
+        assert!((traits.responsiveness() - 0.25).abs() < 1e-4);
+        assert_eq!(traits.oscillator_period(), 100);
+        assert_eq!(traits.long_probe_distance(), 40);
+        assert!((traits.mutation_rate() - 2.0).abs() < 1e-3);
+        assert_eq!(Traits::from_hex(&traits.hex_string()).unwrap(), traits);
+        assert!(Traits::from_hex("0123").is_err());
+    }
+
+    #[test]
+    fn test_traits_are_clamped() {
+        let traits = Traits::new(3.0, 0, 100_000, 1000.0);
+        assert_eq!(traits.responsiveness(), 1.0);
+        assert_eq!(traits.oscillator_period(), 2);
+        assert_eq!(traits.long_probe_distance(), 512);
+        assert_eq!(traits.mutation_rate(), 16.0);
+
+        for _ in 0..100 {
+            let traits = Traits::random();
+            assert!(RESPONSIVENESS_RANGE.contains(&traits.responsiveness()));
+            assert!(OSCILLATOR_PERIOD_RANGE.contains(&traits.oscillator_period()));
+            assert!(LONG_PROBE_DISTANCE_RANGE.contains(&traits.long_probe_distance()));
+        }
+    }
+
+    #[test]
+    fn test_founders_take_the_parameters() {
+        let mut p = Parameters::defaults();
+        p.responsiveness = 0.8;
+        p.long_probe_distance = 24;
+        let traits = Traits::from_parameters(&p);
+        assert!((traits.responsiveness() - 0.8).abs() < 1e-4);
+        assert_eq!(traits.oscillator_period(), DEFAULT_OSCILLATOR_PERIOD);
+        assert_eq!(traits.long_probe_distance(), 24);
+        assert!((traits.mutation_rate() - 1.0).abs() < 1e-3);
+    }
+}
+//</editor-fold>
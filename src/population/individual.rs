@@ -1,22 +1,34 @@
 
 use crate::Parameters;
 use crate::population::brain::NeuralNet;
-use crate::population::brain::sensor_actions::{ENABLED_ACTIONS, ENABLED_SENSORS};
+use crate::population::brain::activation::Activation;
+use crate::population::brain::sensor_actions::{ENABLED_ACTIONS, ENABLED_SENSORS, Wiring};
 use crate::population::brain::sensor_actions::action_implementation::get_action_dispatch;
 use crate::population::brain::sensor_actions::sensor_implementation::get_sensor_dispatch;
-use crate::population::genome::gene::{ACTION, SENSOR};
 use crate::population::genome::Genome;
-use crate::simulation::peeps::{DeathQueue, MoveQueue};
+use crate::population::species::Species;
+use crate::simulation::lineage::IndividualId;
+use crate::simulation::queues::{BirthQueue, DeathQueue, EatQueue, KillQueue, MoveQueue};
 use crate::simulation::signals::Signals;
 use crate::simulation::types::{Coord, Dir};
 use crate::simulation::world::World;
 
+/// Position of an individual in the population, which is also the value its cell holds in the world.
+/// Index 0 belongs to a placeholder individual, so valid indices start at 1.
+pub type IndividualIndex = u32;
+
+// The largest index is reserved for the barriers of the world, see [`Cell`](crate::simulation::world::Cell)
+pub const MAX_POPULATION: IndividualIndex = IndividualIndex::MAX - 1;
+
 pub struct Individual {
     pub alive: bool,
-    pub index: u16, //
+    pub index: IndividualIndex,
+    pub id: IndividualId,
     pub location: Coord,
     pub birth_location: Coord,
     pub age: u32,
+    // Spent by moving and, in the continuous generation mode, by living. Gained by eating.
+    pub energy: f32,
     pub responsiveness: f32,
     pub oscillation_period: u32,
     pub long_probe_distance: u32,
@@ -24,103 +36,87 @@ pub struct Individual {
     pub challenge_bits: u32,
     pub neural_net: NeuralNet,
     pub genome: Genome,
-    pub num_neurons: u16
+    pub num_neurons: u16,
+    // Sensors and actions of the individual's species, see [`Species::sensor_mask`]
+    sensor_mask: u32,
+    action_mask: u32,
+    // The actions of the features the run doesn't use are never taken
+    wiring: Wiring,
 }
 
 impl Individual {
-    pub fn new(index: u16, location: Coord, genome: Genome, p: &Parameters) -> Individual {
+    pub fn new(index: IndividualIndex, id: IndividualId, location: Coord, genome: Genome, p: &Parameters) -> Individual {
+        let species = p.species.get(genome.species as usize);
+        let wiring = Wiring::from_parameters(p);
         Individual {
             alive: true,
             index,
+            id,
             location,
             birth_location: location,
             age: 0,
+            energy: p.initial_energy,
             num_neurons: p.max_number_neurons,
-            responsiveness: 0.5,
-            oscillation_period: 34,
-            long_probe_distance: p.long_probe_distance,
+            responsiveness: genome.traits.responsiveness(),
+            oscillation_period: genome.traits.oscillator_period(),
+            long_probe_distance: genome.traits.long_probe_distance(),
             last_move_direction: Dir::random(),
             challenge_bits: 0,
-            neural_net: NeuralNet::new(&genome, p.max_number_neurons),
+            neural_net: NeuralNet::new(&genome, Individual::activations(&genome, p), p.max_number_neurons, wiring),
+            sensor_mask: species.map_or(u32::MAX, Species::sensor_mask),
+            action_mask: species.map_or(u32::MAX, Species::action_mask),
+            wiring,
             genome
         }
     }
 
-    pub fn get_sensor_value(&self, source_num: u8, population_genomes: &Vec<Genome>, world: &World, signals: &Signals, parameters: &Parameters, simulation_step: u32) -> f32 {
+    // The activations evolve with the genome, unless a single one is used for the whole run
+    fn activations<'g>(genome: &'g Genome, p: &'g Parameters) -> &'g [Activation] {
+        if p.evolve_activations && !genome.activations.is_empty() {
+            return &genome.activations;
+        }
+        return std::slice::from_ref(&p.activation);
+    }
+
+    pub fn get_sensor_value(&self, source_num: u16, population_genomes: &Vec<Genome>, world: &World, signals: &Signals, parameters: &Parameters, simulation_step: u32) -> f32 {
         let sensor = &ENABLED_SENSORS[source_num as usize];
         let sensor_function = get_sensor_dispatch(sensor);
-        return sensor_function(&self, population_genomes, world, signals, parameters, simulation_step);
+        return sensor_function(self, population_genomes, world, signals, parameters, simulation_step);
     }
 
-    pub fn feed_forward(&self, population_genomes: &Vec<Genome>, world: &World, signals: &Signals, parameters: &Parameters, simulation_step: u32) -> [f32; ENABLED_ACTIONS.len()] {
-        // This container is used to return values for all the action outputs. This array
-        // contains one value per action neuron, which is the sum of all its weighted
-        // input connections. The sum has an arbitrary range.
-        let mut output = [0.0; ENABLED_ACTIONS.len()];
-
-        // Weighted inputs to each neuron are summed in neuronAccumulators
-        let mut neuron_accumulators = vec![0.0f32; self.neural_net.neurons.len()];
-
-        // Connections were ordered at birth so that all connections to neurons get
-        // processed here before any connections to actions. As soon as we encounter the
-        // first connection to an action, we'll pass all the neuron input accumulators
-        // through a transfer function and update the neuron outputs in the individual,
-        // except for undriven neurons which act as bias feeds and don't change. The
-        // transfer function will leave each neuron's output in the range -1.0..1.0.
-        let mut neuron_outputs_computed = false;
-        for gene in self.neural_net.connections.iter() {
-            if gene.get_sink_type() == ACTION && !neuron_outputs_computed {
-                // We've handled all the connections from sensors and now we are about to
-                // start on the connections to the action outputs, so now it's time to
-                // update and latch all the neuron outputs to their proper range (-1.0..1.0)
-                for (neuron_index, neuron) in self.neural_net.neurons.iter().enumerate() {
-                    if neuron.borrow().driven {
-                        let neuron_output = neuron_accumulators[neuron_index];
-                        let neuron_output = f32::tanh(neuron_output);
-                        neuron.borrow_mut().output = neuron_output;
-                    }
-                }
-                neuron_outputs_computed = true;
-            }
-
-            // Obtain the connection's input value from a sensor neuron or other neuron
-            // The values are summed for now, later passed through a transfer function
-            let input_value=
-            if gene.get_source_type() == SENSOR {
-                self.get_sensor_value(gene.get_source_num(), population_genomes, world, signals, parameters, simulation_step)
-            } else {
-                let source_neuron = &self.neural_net.neurons[gene.get_source_num() as usize];
-                source_neuron.borrow().output
-            };
-
-            // Weight the connection's value and add to neuron accumulator or action accumulator.
-            // The action and neuron accumulators will therefore contain +- float values in
-            // an arbitrary range.
-            if gene.get_sink_type() == ACTION {
-                output[gene.get_sink_num() as usize] += input_value * gene.weight_as_float();
-            } else {
-                neuron_accumulators[gene.get_sink_num() as usize] += input_value * gene.weight_as_float();
-            }
+    pub fn feed_forward(&mut self, population_genomes: &Vec<Genome>, world: &World, signals: &Signals, parameters: &Parameters, simulation_step: u32) -> [f32; ENABLED_ACTIONS.len()] {
+        // Every sensor the net reads is computed once per step, however many connections read it.
+        // The sensors the species can't use read 0.0.
+        let mut sensor_values = [0.0; ENABLED_SENSORS.len()];
+        for sensor in self.neural_net.used_sensors().iter().filter(|&&s| self.sensor_mask & 1 << s != 0) {
+            sensor_values[*sensor as usize] = self.get_sensor_value(*sensor, population_genomes, world, signals, parameters, simulation_step);
         }
-
-        return output;
+        return self.neural_net.evaluate(&sensor_values);
     }
 
     pub fn response_curve(value: f32, curve_k_factor: f32) -> f32 {
         return (value - 2.0).powf(-2.0 * curve_k_factor) - (2.0f32).powf(-2.0 * curve_k_factor)*(1.0-value);
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn simulate(&mut self, population_genomes: &mut Vec<Genome>, world: &mut World, signals: &mut Signals, parameters: &Parameters,
-                                          death_queue: &mut DeathQueue, move_queue: &mut MoveQueue, simulation_step: u32) {
+                                          death_queue: &mut DeathQueue, birth_queue: &mut BirthQueue, eat_queue: &mut EatQueue, kill_queue: &mut KillQueue,
+                                          move_queue: &mut MoveQueue, simulation_step: u32) {
         self.age += 1;
         let action_levels = self.feed_forward(population_genomes, world, signals, parameters, simulation_step);
         for (i, action) in ENABLED_ACTIONS.iter().enumerate() {
+            if self.action_mask & 1 << *action as u32 == 0 || !self.wiring.has_action(i) {
+                continue;
+            }
             let action_executor = get_action_dispatch(action);
             let level = action_levels[i];
             action_executor(
                 self,
                 move_queue,
                 death_queue,
+                birth_queue,
+                eat_queue,
+                kill_queue,
                 parameters,
                 level
             );
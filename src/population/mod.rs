@@ -1,4 +1,5 @@
 #![allow(dead_code)]
 pub mod individual;
 pub mod genome;
-pub mod brain;
\ No newline at end of file
+pub mod brain;
+pub mod species;
\ No newline at end of file
@@ -0,0 +1,88 @@
+use serde::{Serialize, Deserialize};
+use crate::Parameters;
+use crate::population::brain::sensor_actions::{Action, ENABLED_ACTIONS, ENABLED_SENSORS, Sensor};
+use crate::simulation::peeps::survival_criteria::Challenges;
+
+/// Position of a species in the `species` parameter. Without species, everybody belongs to species 0.
+pub type SpeciesId = u8;
+
+// The sensors and actions a species can use are kept as bit masks
+const _: () = assert!(ENABLED_SENSORS.len() <= 32);
+const _: () = assert!(ENABLED_ACTIONS.len() <= 32);
+
+/// A group of individuals that only breed among themselves. Each species can be limited to some of
+/// the sensors and actions, and face its own survival challenge.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Species {
+    pub name: String,
+    // Individuals of the species in each generation
+    pub population: u32,
+    // The sensors and actions the brains of the species can use. All of them when empty.
+    #[serde(default)]
+    pub sensors: Vec<Sensor>,
+    #[serde(default)]
+    pub actions: Vec<Action>,
+    // Survival criterion of the species, instead of the population's one
+    #[serde(default)]
+    pub challenge: Option<Challenges>,
+}
+
+impl Species {
+    /// Bit `n` is set when the species can use sensor `n`.
+    pub fn sensor_mask(&self) -> u32 {
+        if self.sensors.is_empty() {
+            return u32::MAX;
+        }
+        return self.sensors.iter().fold(0, |mask, sensor| mask | 1 << *sensor as u32);
+    }
+
+    /// Bit `n` is set when the species can use action `n`.
+    pub fn action_mask(&self) -> u32 {
+        if self.actions.is_empty() {
+            return u32::MAX;
+        }
+        return self.actions.iter().fold(0, |mask, action| mask | 1 << *action as u32);
+    }
+}
+
+/// Species of the individual at `index` (starting at 1) of a newly populated generation. The
+/// species take consecutive ranges of indices, in the order they are listed.
+pub fn species_at_index(p: &Parameters, index: u32) -> SpeciesId {
+    let mut end = 0;
+    for (id, species) in p.species.iter().enumerate() {
+        end += species.population;
+        if index <= end {
+            return id as SpeciesId;
+        }
+    }
+    return 0;
+}
+
+//<editor-fold desc="Unit tests">
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_species_layout() {
+        let mut p = Parameters::defaults();
+        p.population = 5;
+        p.species = serde_yaml::from_str("
+            - name: prey
+              population: 3
+              sensors: [loc_x, species_fwd]
+            - name: predator
+              population: 2
+              actions: [move_forward, kill_forward]
+              challenge: energy
+        ").unwrap();
+        let species: Vec<SpeciesId> = (1..=5).map(|i| species_at_index(&p, i)).collect();
+        assert_eq!(species, vec![0, 0, 0, 1, 1]);
+
+        assert_eq!(p.species[0].sensor_mask(), 1 << Sensor::LocX as u32 | 1 << Sensor::SpeciesFwd as u32);
+        assert_eq!(p.species[0].action_mask(), u32::MAX);
+        assert_eq!(p.species[1].action_mask(), 1 << Action::MoveForward as u32 | 1 << Action::KillForward as u32);
+        assert_eq!(p.species[1].challenge, Some(Challenges::Energy));
+    }
+}
+//</editor-fold>
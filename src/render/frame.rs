@@ -0,0 +1,249 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+use serde::{Serialize, Deserialize};
+use crate::population::genome::Genome;
+use crate::population::individual::Individual;
+use crate::simulation::signals::Signals;
+use crate::simulation::types::Coord;
+use crate::simulation::world::World;
+
+const BACKGROUND_COLOR: [u8; 3] = [255, 255, 255];
+const BARRIER_COLOR: [u8; 3] = [136, 136, 136];
+// Signal layers take these colors in turn
+const SIGNAL_COLORS: [[u8; 3]; 3] = [[200, 100, 255], [255, 160, 60], [60, 190, 230]];
+const FOOD_COLOR: [u8; 3] = [120, 200, 90];
+const REPLAY_COLOR: [u8; 3] = [176, 32, 32];
+
+// Largest frame, in bytes, so that the pixel offsets fit in a u32
+const MAX_FRAME_BYTES: u64 = u32::MAX as u64;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageFormat {
+    Png,
+    Ppm,
+}
+
+impl ImageFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Ppm => "ppm",
+        }
+    }
+}
+
+/// Derives a display color from the genome, so that individuals with similar genomes are drawn with
+/// similar colors. Like biosim4, only the first and last genes are taken into account.
+pub fn genetic_color(genome: &Genome) -> [u8; 3] {
+    let (first, last) = match (genome.first(), genome.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return [0, 0, 0],
+    };
+
+    let bits = (first.get_source_type() as u8)
+        | (last.get_source_type() as u8) << 1
+        | (first.get_sink_type() as u8) << 2
+        | (last.get_sink_type() as u8) << 3
+        | ((first.get_source_num() & 1) as u8) << 4
+        | ((first.get_sink_num() & 1) as u8) << 5
+        | ((last.get_source_num() & 1) as u8) << 6
+        | ((last.get_sink_num() & 1) as u8) << 7;
+
+    // Keep the colors away from the white background
+    const MAX_COLOR_VALUE: u8 = 0xb0;
+    let r = bits;
+    let g = (bits & 0x1f) << 3;
+    let b = (bits & 7) << 5;
+    return [r.min(MAX_COLOR_VALUE), g.min(MAX_COLOR_VALUE), b.min(MAX_COLOR_VALUE)];
+}
+
+/// RGB raster of the world, `scale` pixels per grid cell. The world's y axis points north, so the
+/// rows are flipped to have north at the top of the image.
+pub struct Frame {
+    pub width: u32,
+    pub height: u32,
+    pub scale: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Frame {
+    /// Panics unless [`Frame::check_size`] accepts the size.
+    pub fn new(world_width: u16, world_height: u16, scale: u32) -> Frame {
+        if let Err(e) = Frame::check_size(world_width, world_height, scale) {
+            panic!("{}", e);
+        }
+        let width = world_width as u32 * scale;
+        let height = world_height as u32 * scale;
+        let pixels = BACKGROUND_COLOR.repeat(width as usize * height as usize);
+        return Frame { width, height, scale, pixels };
+    }
+
+    /// Checks that a world can be rendered with `scale` pixels per cell.
+    pub fn check_size(world_width: u16, world_height: u16, scale: u32) -> Result<(), Box<dyn Error>> {
+        if scale == 0 {
+            return Err("display_scale must be at least 1".into());
+        }
+        let bytes = world_width as u64 * world_height as u64 * scale as u64 * scale as u64 * 3;
+        if bytes > MAX_FRAME_BYTES {
+            return Err(format!("A {}x{} world at display_scale {} makes frames of {} bytes, more than the {} allowed",
+                               world_width, world_height, scale, bytes, MAX_FRAME_BYTES).into());
+        }
+        return Ok(());
+    }
+
+    /// Renders the barriers, the food, every signal layer and every living individual.
+    pub fn render(world: &World, signals: &Signals, population: &[Individual], scale: u32) -> Frame {
+        let mut frame = Frame::new(world.width, world.height, scale);
+
+        for x in 0..world.width as i16 {
+            for y in 0..world.height as i16 {
+                let location = Coord(x, y);
+                if world.is_barrier_at(location) {
+                    frame.fill_cell(location, BARRIER_COLOR);
+                    continue;
+                }
+                let mut color = BACKGROUND_COLOR;
+                let food = world.food.get(location) / world.food.max_per_cell;
+                if food > 0.0 {
+                    color = blend(color, FOOD_COLOR, food);
+                }
+                for layer in 0..signals.num_layers() {
+                    let intensity = u16::min(signals.get(layer, location), 255) as f32 / 255.0;
+                    if intensity > 0.0 {
+                        color = blend(color, SIGNAL_COLORS[layer % SIGNAL_COLORS.len()], intensity);
+                    }
+                }
+                if color != BACKGROUND_COLOR {
+                    frame.fill_cell(location, color);
+                }
+            }
+        }
+
+        for individual in population.iter().skip(1).filter(|i| i.alive) {
+            frame.fill_cell(individual.location, genetic_color(&individual.genome));
+        }
+
+        return frame;
+    }
+
+    /// Renders bare locations, as rebuilt from a replay log where genomes aren't available.
+    pub fn render_locations(world_width: u16, world_height: u16, locations: &[Coord], scale: u32) -> Frame {
+        let mut frame = Frame::new(world_width, world_height, scale);
+        for location in locations {
+            frame.fill_cell(*location, REPLAY_COLOR);
+        }
+        return frame;
+    }
+
+    pub fn fill_cell(&mut self, location: Coord, color: [u8; 3]) {
+        let world_height = self.height / self.scale;
+        if location.0 < 0 || location.1 < 0 || location.0 as u32 >= self.width / self.scale || location.1 as u32 >= world_height {
+            return;
+        }
+
+        let left = location.0 as u32 * self.scale;
+        let top = (world_height - 1 - location.1 as u32) * self.scale;
+        for y in top..top + self.scale {
+            for x in left..left + self.scale {
+                let offset = (y as usize * self.width as usize + x as usize) * 3;
+                self.pixels[offset..offset + 3].copy_from_slice(&color);
+            }
+        }
+    }
+
+    pub fn save(&self, path: &Path, format: ImageFormat) -> Result<(), Box<dyn Error>> {
+        match format {
+            ImageFormat::Png => self.write_png(path),
+            ImageFormat::Ppm => self.write_ppm(path),
+        }
+    }
+
+    // Binary portable pixmap (P6), readable by most image tools without any extra dependency.
+    pub fn write_ppm(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        write!(writer, "P6\n{} {}\n255\n", self.width, self.height)?;
+        writer.write_all(&self.pixels)?;
+        return Ok(());
+    }
+
+    /// Reads a PNG image of any color type as a frame with a scale of 1. Gray images are expanded
+    /// to RGB, and transparency is dropped.
+    pub fn read_png(path: &Path) -> Result<Frame, Box<dyn Error>> {
+        let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info()?;
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer)?;
+        let mut pixels = Vec::with_capacity(info.width as usize * info.height as usize * 3);
+        for pixel in buffer[..info.buffer_size()].chunks(info.color_type.samples()) {
+            match pixel.len() {
+                // Gray, possibly with alpha
+                1 | 2 => pixels.extend_from_slice(&[pixel[0]; 3]),
+                _ => pixels.extend_from_slice(&pixel[..3]),
+            }
+        }
+        return Ok(Frame { width: info.width, height: info.height, scale: 1, pixels });
+    }
+
+    /// Color of the pixel at column `x` and row `y`, counted from the top.
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 3] {
+        let offset = ((y * self.width + x) * 3) as usize;
+        return [self.pixels[offset], self.pixels[offset + 1], self.pixels[offset + 2]];
+    }
+
+    pub fn write_png(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let writer = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(writer, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&self.pixels)?;
+        return Ok(());
+    }
+}
+
+fn blend(from: [u8; 3], to: [u8; 3], amount: f32) -> [u8; 3] {
+    let mut color = [0u8; 3];
+    for i in 0..3 {
+        color[i] = (from[i] as f32 + (to[i] as f32 - from[i] as f32) * amount) as u8;
+    }
+    return color;
+}
+
+//<editor-fold desc="Unit tests">
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fill_cell_flips_y_axis() {
+        let mut frame = Frame::new(2, 2, 1);
+        frame.fill_cell(Coord(0, 0), [1, 2, 3]);
+        // (0, 0) is the south-west corner, which is the first pixel of the last row
+        assert_eq!(&frame.pixels[6..9], &[1, 2, 3]);
+        assert_eq!(&frame.pixels[0..3], &BACKGROUND_COLOR);
+    }
+
+    #[test]
+    fn test_render_signal_layers() {
+        let world = World::new(3, 1, crate::simulation::grid::Topology::Bounded);
+        let mut signals = Signals::new(2, 3, 1);
+        signals.set(0, Coord(0, 0), 255);
+        signals.set(1, Coord(1, 0), 255);
+        let frame = Frame::render(&world, &signals, &[], 1);
+        assert_eq!(frame.pixel(0, 0), SIGNAL_COLORS[0]);
+        assert_eq!(frame.pixel(1, 0), SIGNAL_COLORS[1]);
+        assert_eq!(frame.pixel(2, 0), BACKGROUND_COLOR);
+    }
+
+    #[test]
+    fn test_fill_cell_out_of_bounds() {
+        let mut frame = Frame::new(2, 2, 2);
+        frame.fill_cell(Coord(2, 0), [1, 2, 3]);
+        frame.fill_cell(Coord(-1, 0), [1, 2, 3]);
+        assert!(frame.pixels.chunks(3).all(|p| p == BACKGROUND_COLOR));
+    }
+}
+//</editor-fold>
@@ -0,0 +1,126 @@
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use crate::Parameters;
+use crate::render::frame::Frame;
+use crate::simulation::simulation::Simulation;
+
+// Delay between two frames of the animations, in hundredths of a second.
+const GIF_FRAME_DELAY: u16 = 4;
+
+/// Captures a frame for every step of the selected generations. The frames are saved as individual
+/// images, and assembled into an animated GIF at the end of the generation.
+///
+/// The recorder only reads the simulation, so it works the same in the terminal UI and in headless runs.
+pub struct Recorder {
+    directory: PathBuf,
+    frames: Vec<Frame>,
+    recording_generation: Option<u32>,
+}
+
+impl Recorder {
+    pub fn new(p: &Parameters) -> Recorder {
+        return Recorder {
+            directory: PathBuf::from(&p.output_directory).join("images"),
+            frames: Vec::new(),
+            recording_generation: None,
+        };
+    }
+
+    /// Returns true if the steps of `generation` should be captured. The first
+    /// `video_save_first_frames` generations are always captured, then one every `video_stride`.
+    pub fn should_record(p: &Parameters, generation: u32) -> bool {
+        if !p.save_video && !p.save_frames {
+            return false;
+        }
+        return generation < p.video_save_first_frames || (p.video_stride > 0 && generation.is_multiple_of(p.video_stride));
+    }
+
+    /// Call before every simulation step. Captures the world if the current generation is selected and
+    /// finishes the animation of the previous generation once the simulation moved on.
+    pub fn capture(&mut self, simulation: &Simulation) -> Result<(), Box<dyn Error>> {
+        let p = simulation.parameters;
+        if let Some(generation) = self.recording_generation {
+            if generation != simulation.generation {
+                self.finish_generation(p)?;
+            }
+        }
+
+        if !Recorder::should_record(p, simulation.generation) {
+            return Ok(());
+        }
+
+        if self.recording_generation.is_none() {
+            fs::create_dir_all(&self.directory)?;
+        }
+
+        // With several islands, only the first one is recorded
+        let peeps = &simulation.islands[0].peeps;
+        let frame = Frame::render(&peeps.world, &peeps.signals, &peeps.population, p.display_scale);
+        if p.save_frames {
+            let step = simulation.simulation_step % p.steps_per_generation as u32;
+            let file_name = format!("frame-{:06}-{:05}.{}", simulation.generation, step, p.image_format.extension());
+            frame.save(&self.directory.join(file_name), p.image_format)?;
+        }
+        if p.save_video {
+            self.frames.push(frame);
+        }
+        self.recording_generation = Some(simulation.generation);
+        return Ok(());
+    }
+
+    /// Writes the animation of the generation being recorded, if any.
+    pub fn finish_generation(&mut self, p: &Parameters) -> Result<(), Box<dyn Error>> {
+        let generation = match self.recording_generation.take() {
+            Some(generation) => generation,
+            None => return Ok(()),
+        };
+
+        if p.save_video && !self.frames.is_empty() {
+            let path = self.directory.join(format!("gen-{:06}.gif", generation));
+            write_gif(&path, &self.frames)?;
+        }
+        self.frames.clear();
+        return Ok(());
+    }
+}
+
+/// Fails without writing anything if there are no frames, or if they are larger than the 65535
+/// pixels a GIF allows on each side.
+pub fn write_gif(path: &Path, frames: &[Frame]) -> Result<(), Box<dyn Error>> {
+    let first = frames.first().ok_or("An animation needs at least one frame")?;
+    let (width, height) = match (u16::try_from(first.width), u16::try_from(first.height)) {
+        (Ok(width), Ok(height)) => (width, height),
+        _ => return Err(format!("Frames of {}x{} pixels are too large for a GIF, lower display_scale",
+                                first.width, first.height).into()),
+    };
+    let writer = BufWriter::new(File::create(path)?);
+    let mut encoder = gif::Encoder::new(writer, width, height, &[])?;
+    encoder.set_repeat(gif::Repeat::Infinite)?;
+    for frame in frames {
+        let mut gif_frame = gif::Frame::from_rgb(width, height, &frame.pixels);
+        gif_frame.delay = GIF_FRAME_DELAY;
+        encoder.write_frame(&gif_frame)?;
+    }
+    return Ok(());
+}
+
+//<editor-fold desc="Unit tests">
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_write_gif_rejects_invalid_frames() {
+        let path = std::env::temp_dir().join(format!("biosim-gif-{}.gif", std::process::id()));
+        assert!(write_gif(&path, &[]).is_err());
+        assert!(write_gif(&path, &[Frame::new(40_000, 1, 2)]).is_err());
+        assert!(!path.exists());
+
+        write_gif(&path, &[Frame::new(4, 3, 2), Frame::new(4, 3, 2)]).unwrap();
+        assert!(fs::metadata(&path).unwrap().len() > 0);
+        fs::remove_file(&path).unwrap();
+    }
+}
+//</editor-fold>
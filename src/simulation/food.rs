@@ -0,0 +1,213 @@
+use rand::Rng;
+use serde::{Serialize, Deserialize};
+use crate::Parameters;
+use crate::simulation::grid::{Grid, Neighborhood, NeighborhoodShape, Topology};
+use crate::simulation::map::WorldMap;
+use crate::simulation::random;
+use crate::simulation::types::{Coord, Dir};
+
+/// Where food grows in the world.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FoodPattern {
+    // There's no food at all
+    None,
+    // Food grows everywhere at the same rate
+    Uniform,
+    // Food grows in `food_patch_count` random discs of radius `food_patch_radius`
+    Patches,
+    // Food grows faster from the west edge to the east edge
+    Gradient,
+    // Food grows in the food zone of the map file
+    Map,
+}
+
+/// Amount of food in every cell of the world. Food grows back at each step in the fertile cells,
+/// up to `food_max_per_cell`, and is depleted by the individuals that eat it.
+pub struct Food {
+    amounts: Grid<f32>,
+    // Fertile cells, with the fraction of the growth rate they get
+    fertile: Vec<(Coord, f32)>,
+    pub max_per_cell: f32,
+    growth_rate: f32,
+}
+
+impl Food {
+    /// A world without food.
+    pub fn empty(width: u16, height: u16, topology: Topology) -> Food {
+        return Food { amounts: Grid::with_topology(width, height, topology), fertile: Vec::new(), max_per_cell: 1.0, growth_rate: 0.0 };
+    }
+
+    /// Lays out the fertile cells with the pattern of the parameters, and fills them up.
+    pub fn new(p: &Parameters, map: Option<&WorldMap>) -> Food {
+        let mut food = Food::empty(p.size_x, p.size_y, p.topology);
+        food.max_per_cell = p.food_max_per_cell;
+        food.growth_rate = p.food_growth_rate;
+        let (width, height) = (p.size_x as i16, p.size_y as i16);
+        match p.food_pattern {
+            FoodPattern::None => {}
+            FoodPattern::Uniform => {
+                for y in 0..height {
+                    for x in 0..width {
+                        food.fertile.push((Coord(x, y), 1.0));
+                    }
+                }
+            }
+            FoodPattern::Patches => {
+                let mut rng = random::rng();
+                let mut fertility = Grid::<f32>::with_topology(p.size_x, p.size_y, p.topology);
+                let neighborhood = Neighborhood::new(NeighborhoodShape::Euclidean, p.food_patch_radius as i16);
+                for _ in 0..p.food_patch_count {
+                    let center = Coord(rng.gen_range(0..width), rng.gen_range(0..height));
+                    let mut cells = Vec::new();
+                    fertility.apply_neighborhood_to_f(center, neighborhood, |c| cells.push(c));
+                    for cell in cells {
+                        fertility.set_at_coord(cell, 1.0);
+                    }
+                }
+                for y in 0..height {
+                    for x in 0..width {
+                        if fertility.at_coord(Coord(x, y)) > 0.0 {
+                            food.fertile.push((Coord(x, y), 1.0));
+                        }
+                    }
+                }
+            }
+            FoodPattern::Map => {
+                let zone = map.map_or(&[][..], |map| &map.food_zone);
+                food.fertile.extend(zone.iter().map(|location| (*location, 1.0)));
+            }
+            FoodPattern::Gradient => {
+                for y in 0..height {
+                    for x in 0..width {
+                        food.fertile.push((Coord(x, y), (x + 1) as f32 / width as f32));
+                    }
+                }
+            }
+        }
+        food.refill();
+        return food;
+    }
+
+    pub fn get(&self, location: Coord) -> f32 {
+        return self.amounts.at_coord(location);
+    }
+
+    /// Fills every fertile cell up to the maximum, as at the start of a generation.
+    pub fn refill(&mut self) {
+        self.amounts.zero_fill();
+        for (location, _) in self.fertile.iter() {
+            self.amounts.set_at_coord(*location, self.max_per_cell);
+        }
+    }
+
+    pub fn grow(&mut self) {
+        if self.growth_rate <= 0.0 {
+            return;
+        }
+        for (location, fertility) in self.fertile.iter() {
+            let amount = self.amounts.at_coord(*location) + self.growth_rate * fertility;
+            self.amounts.set_at_coord(*location, f32::min(amount, self.max_per_cell));
+        }
+    }
+
+    /// Removes up to `amount` of food from the cell, and returns how much was there to take.
+    pub fn take(&mut self, location: Coord, amount: f32) -> f32 {
+        let available = self.amounts.at_coord(location);
+        let taken = f32::min(available, amount);
+        self.amounts.set_at_coord(location, available - taken);
+        return taken;
+    }
+
+    /// Food in the neighborhood, relative to the most it could hold: 0.0..1.0.
+    pub fn density(&self, location: Coord, neighborhood: Neighborhood) -> f32 {
+        let (mut total, mut cells) = (0.0, 0);
+        self.amounts.apply_neighborhood_to_f(location, neighborhood, |c| {
+            total += self.amounts.at_coord(c);
+            cells += 1;
+        });
+        return total / (cells as f32 * self.max_per_cell);
+    }
+
+    /// Food in the `distance` cells ahead of `location`, relative to the most they could hold:
+    /// 0.0..1.0. Cells beyond the edge of a bounded world count as empty.
+    pub fn density_forward(&self, location: Coord, direction: Dir, distance: u32) -> f32 {
+        let offset: Coord = direction.into();
+        if offset == Coord(0, 0) || distance == 0 {
+            return 0.0;
+        }
+        let mut total = 0.0;
+        let mut current = location;
+        for _ in 0..distance {
+            match self.amounts.resolve(current + offset) {
+                Some(next) => current = next,
+                None => break,
+            }
+            total += self.amounts.at_coord(current);
+        }
+        return total / (distance as f32 * self.max_per_cell);
+    }
+}
+
+//<editor-fold desc="Unit tests">
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parameters(pattern: FoodPattern) -> Parameters {
+        let mut p = Parameters::defaults();
+        p.size_x = 20;
+        p.size_y = 10;
+        p.food_pattern = pattern;
+        p.food_max_per_cell = 4.0;
+        p.food_growth_rate = 1.0;
+        return p;
+    }
+
+    #[test]
+    fn test_food_grows_back_after_eating() {
+        let mut food = Food::new(&parameters(FoodPattern::Uniform), None);
+        assert_eq!(food.get(Coord(3, 3)), 4.0);
+        assert_eq!(food.take(Coord(3, 3), 3.0), 3.0);
+        assert_eq!(food.take(Coord(3, 3), 3.0), 1.0);
+        assert_eq!(food.get(Coord(3, 3)), 0.0);
+
+        food.grow();
+        assert_eq!(food.get(Coord(3, 3)), 1.0);
+        for _ in 0..10 {
+            food.grow();
+        }
+        assert_eq!(food.get(Coord(3, 3)), 4.0);
+    }
+
+    #[test]
+    fn test_patterns() {
+        let none = Food::new(&parameters(FoodPattern::None), None);
+        assert_eq!(none.density(Coord(5, 5), Neighborhood::new(NeighborhoodShape::Moore, 3)), 0.0);
+
+        let mut gradient = Food::new(&parameters(FoodPattern::Gradient), None);
+        gradient.amounts.zero_fill();
+        gradient.grow();
+        assert!(gradient.get(Coord(0, 0)) < gradient.get(Coord(19, 0)));
+        assert_eq!(gradient.get(Coord(19, 0)), 1.0);
+
+        let mut p = parameters(FoodPattern::Patches);
+        p.food_patch_count = 1;
+        p.food_patch_radius = 2;
+        // The patch can't be clipped by an edge
+        p.topology = Topology::Torus;
+        let patches = Food::new(&p, None);
+        assert_eq!(patches.fertile.len(), 13);
+    }
+
+    #[test]
+    fn test_density_forward() {
+        let mut food = Food::new(&parameters(FoodPattern::Uniform), None);
+        food.take(Coord(6, 5), 4.0);
+        assert_eq!(food.density_forward(Coord(5, 5), Dir::from(Coord(1, 0)), 2), 0.5);
+        assert_eq!(food.density_forward(Coord(5, 5), Dir::from(Coord(-1, 0)), 2), 1.0);
+        // Nothing beyond the edge
+        assert_eq!(food.density_forward(Coord(19, 5), Dir::from(Coord(1, 0)), 2), 0.0);
+    }
+}
+//</editor-fold>
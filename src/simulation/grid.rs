@@ -1,69 +1,148 @@
+use serde::{Serialize, Deserialize};
 use crate::simulation::types::Coord;
 
-//<editor-fold desc="Column implementation">
-struct Column {
-    data: Vec<u16>,
+/// How the edges of the grid behave. In a `Torus`, leaving the grid through one edge enters it
+/// through the opposite one, so there are no boundaries at all.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Topology {
+    Bounded,
+    Torus,
 }
 
-impl Column {
-    fn new(size: usize) -> Column {
-        Column {
-            data: vec![0; size],
+/// Shape of the region visited around a location, see [`Grid::apply_neighborhood_to_f`].
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NeighborhoodShape {
+    // Cells within the given Manhattan distance: a diamond
+    VonNeumann,
+    // Cells within the given Chebyshev distance: a square
+    Moore,
+    // Cells within the given Euclidean distance: a circle
+    Euclidean,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Neighborhood {
+    pub shape: NeighborhoodShape,
+    pub radius: i16,
+}
+
+impl Neighborhood {
+    pub fn new(shape: NeighborhoodShape, radius: i16) -> Neighborhood {
+        return Neighborhood { shape, radius };
+    }
+
+    /// Half the width of the row of cells `dy` rows away from the center: the row spans from
+    /// -half_width to half_width. Negative if the row is outside the neighborhood. The shapes are
+    /// symmetric, so this is also the half height of the column `dy` columns away.
+    #[inline]
+    pub fn half_width(&self, dy: i16) -> i16 {
+        let dx = dy.abs();
+        if dx > self.radius {
+            return -1;
+        }
+        match self.shape {
+            NeighborhoodShape::VonNeumann => self.radius - dx,
+            NeighborhoodShape::Moore => self.radius,
+            NeighborhoodShape::Euclidean => {
+                let (dx, r) = (dx as i32, self.radius as i32);
+                let mut h = ((r * r - dx * dx) as f32).sqrt() as i32;
+                // Correct any rounding of the square root
+                while (h + 1) * (h + 1) + dx * dx <= r * r {
+                    h += 1;
+                }
+                while h * h + dx * dx > r * r {
+                    h -= 1;
+                }
+                h as i16
+            }
         }
     }
 
-    fn len(&self) -> usize {
-        self.data.len()
+    /// Returns true if a cell at the given offset from the center belongs to the neighborhood.
+    #[inline]
+    pub fn contains(&self, offset: Coord) -> bool {
+        match self.shape {
+            NeighborhoodShape::VonNeumann => offset.0.abs() + offset.1.abs() <= self.radius,
+            NeighborhoodShape::Moore => offset.0.abs() <= self.radius && offset.1.abs() <= self.radius,
+            NeighborhoodShape::Euclidean => {
+                let (x, y, r) = (offset.0 as i32, offset.1 as i32, self.radius as i32);
+                x * x + y * y <= r * r
+            }
+        }
     }
 }
 
-impl std::ops::Index<usize> for Column {
-    type Output = u16;
+/// Cells are stored in a single row-major buffer: the cell (x, y) is at `y * width + x`. New cells
+/// hold the default value of `T`, which counts as empty.
+pub struct Grid<T = u16> {
+    pub width: u16,
+    pub height: u16,
+    pub topology: Topology,
+    data: Vec<T>,
+}
 
-    fn index(&self, index: usize) -> &u16 {
-        &self.data[index]
+impl<T: Copy + Default + PartialEq> Grid<T> {
+    pub fn new(width: u16, height: u16) -> Grid<T> {
+        return Grid::with_topology(width, height, Topology::Bounded);
     }
-}
 
-impl std::ops::IndexMut<usize> for Column {
-    fn index_mut(&mut self, index: usize) -> &mut u16 {
-        &mut self.data[index]
+    pub fn with_topology(width: u16, height: u16, topology: Topology) -> Grid<T> {
+        let data = vec![T::default(); width as usize * height as usize];
+        return Grid { width, height, topology, data };
     }
-}
 
-//</editor-fold>
+    #[inline]
+    fn index_of(&self, x: u16, y: u16) -> usize {
+        return y as usize * self.width as usize + x as usize;
+    }
 
-pub const EMPTY_CELL: u16 = 0;
+    // Out of range coordinates would silently alias another row, so the accessors check both axes.
+    #[inline]
+    pub fn at(&self, x: u16, y: u16) -> T {
+        assert!(x < self.width && y < self.height, "({}, {}) is outside the grid", x, y);
+        self.data[self.index_of(x, y)]
+    }
 
-pub struct Grid {
-    pub width: u16,
-    pub height: u16,
-    data: Vec<Column>,
-}
+    #[inline]
+    pub fn at_coord(&self, location: Coord) -> T {
+        self.at(location.0 as u16, location.1 as u16)
+    }
 
-impl Grid {
-    pub fn new(width: u16, height: u16) -> Grid {
-        let mut data = Vec::with_capacity(width as usize);
-        for _ in 0..width {
-            data.push(Column::new(height as usize));
+    /// Returns None instead of panicking when the location is outside the grid.
+    #[inline]
+    pub fn get(&self, location: Coord) -> Option<T> {
+        if !self.is_in_bounds(location) {
+            return None;
         }
-        return Grid { width, height, data };
+        return Some(self.data[self.index_of(location.0 as u16, location.1 as u16)]);
     }
 
-    pub fn at(&self, x: u16, y: u16) -> u16 {
-        self.data[x as usize][y as usize]
+    /// # Safety
+    /// `location` must be inside the grid, see [`Grid::is_in_bounds`].
+    #[inline]
+    pub unsafe fn at_coord_unchecked(&self, location: Coord) -> T {
+        debug_assert!(self.is_in_bounds(location));
+        *self.data.get_unchecked(self.index_of(location.0 as u16, location.1 as u16))
     }
 
-    pub fn at_coord(&self, location: Coord) -> u16 {
-        self.data[location.0 as usize][location.1 as usize]
+    /// The row-major cell buffer, for scans that walk it directly.
+    #[inline]
+    pub(crate) fn cells(&self) -> &[T] {
+        return &self.data;
     }
 
-    pub fn set_at(&mut self, x: u16, y: u16, value: u16) {
-        self.data[x as usize][y as usize] = value;
+    #[inline]
+    pub fn set_at(&mut self, x: u16, y: u16, value: T) {
+        assert!(x < self.width && y < self.height, "({}, {}) is outside the grid", x, y);
+        let index = self.index_of(x, y);
+        self.data[index] = value;
     }
 
-    pub fn set_at_coord(&mut self, location: Coord, value: u16) {
-        self.data[location.0 as usize][location.1 as usize] = value;
+    #[inline]
+    pub fn set_at_coord(&mut self, location: Coord, value: T) {
+        self.set_at(location.0 as u16, location.1 as u16, value);
     }
 
     #[inline]
@@ -71,40 +150,205 @@ impl Grid {
         return location.0 < self.width as i16 && location.1 < self.height as i16 && location.0 >= 0 && location.1 >= 0;
     }
 
+    /// Wraps the location around the edges in a torus. Bounded grids return the location unchanged.
+    #[inline]
+    pub fn wrap(&self, location: Coord) -> Coord {
+        match self.topology {
+            Topology::Bounded => location,
+            Topology::Torus => Coord(location.0.rem_euclid(self.width as i16), location.1.rem_euclid(self.height as i16)),
+        }
+    }
+
+    /// Maps a location, possibly obtained by adding an offset to a valid location, to the cell it
+    /// refers to. Returns None if the location falls outside a bounded grid.
+    #[inline]
+    pub fn resolve(&self, location: Coord) -> Option<Coord> {
+        let location = self.wrap(location);
+        if self.is_in_bounds(location) {
+            return Some(location);
+        }
+        return None;
+    }
+
+    /// Shortest offset that leads from `from` to `to`, which in a torus may cross an edge.
+    pub fn displacement(&self, from: Coord, to: Coord) -> Coord {
+        let offset = to - from;
+        match self.topology {
+            Topology::Bounded => offset,
+            Topology::Torus => Coord(shortest_offset(offset.0, self.width as i16),
+                                     shortest_offset(offset.1, self.height as i16)),
+        }
+    }
+
     #[inline]
     pub fn is_empty_at(&self, location: Coord) -> bool {
-        return self.at_coord(location) == EMPTY_CELL;
+        return self.at_coord(location) == T::default();
     }
 
+    // A torus has no border
     #[inline]
     pub fn is_border_at(&self, location: Coord) -> bool {
+        if self.topology == Topology::Torus {
+            return false;
+        }
         return location.0 == 0 || location.0 == self.width as i16 - 1
             || location.1 == 0 || location.1 == self.height as i16 - 1;
     }
 
     pub fn zero_fill(&mut self) {
-        for column in self.data.iter_mut() {
-            for cell in column.data.iter_mut() {
-                *cell = EMPTY_CELL;
-            }
-        }
+        self.data.fill(T::default());
     }
 
-    pub fn apply_neighborhood_to_f<F>(&self, location: Coord, radius: i16, mut f: F) 
+    pub fn apply_neighborhood_to_f<F>(&self, location: Coord, neighborhood: Neighborhood, mut f: F)
         where F: FnMut(Coord) {
-        // Visits the Von Neumann neighborhood of the given location.
+        // Visits the neighborhood of the given location, including the location itself, row by row.
         // Then calls the given function on each of the visited locations.
-        let mut x = location.0 - radius;
-        while x <= location.0 + radius {
-            let mut y = location.1 - radius;
-            while y <= location.1 + radius {
-                let neighbor = Coord(x, y);
-                if self.is_in_bounds(neighbor) {
+        // In a torus the neighborhood wraps around the edges; if the radius is larger than half
+        // the grid, some cells are visited more than once.
+        let radius = neighborhood.radius;
+        for dy in -radius..=radius {
+            let half_width = neighborhood.half_width(dy);
+            for dx in -half_width..=half_width {
+                if let Some(neighbor) = self.resolve(Coord(location.0 + dx, location.1 + dy)) {
                     f(neighbor);
                 }
-                y += 1;
             }
-            x += 1;
         }
     }
-}
\ No newline at end of file
+
+    /// Number of cells visited by [`Grid::apply_neighborhood_to_f`], computed without visiting them.
+    pub fn neighborhood_size(&self, location: Coord, neighborhood: Neighborhood) -> u32 {
+        let radius = neighborhood.radius;
+        let mut size = 0;
+        for dy in -radius..=radius {
+            let half_width = neighborhood.half_width(dy);
+            if half_width < 0 {
+                continue;
+            }
+            let (left, right) = (location.0 - half_width, location.0 + half_width);
+            match self.topology {
+                Topology::Torus => size += (right - left + 1) as u32,
+                Topology::Bounded => {
+                    let y = location.1 + dy;
+                    if y < 0 || y >= self.height as i16 {
+                        continue;
+                    }
+                    let (left, right) = (i16::max(left, 0), i16::min(right, self.width as i16 - 1));
+                    if right >= left {
+                        size += (right - left + 1) as u32;
+                    }
+                }
+            }
+        }
+        return size;
+    }
+}
+
+// Brings an offset along an axis of the given length into -length/2..=length/2
+fn shortest_offset(offset: i16, length: i16) -> i16 {
+    let offset = offset.rem_euclid(length);
+    if offset > length / 2 {
+        return offset - length;
+    }
+    return offset;
+}
+
+//<editor-fold desc="Unit tests">
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_row_major_accessors() {
+        let mut grid: Grid = Grid::new(3, 2);
+        grid.set_at_coord(Coord(2, 1), 7);
+        assert_eq!(grid.at(2, 1), 7);
+        assert_eq!(grid.get(Coord(2, 1)), Some(7));
+        assert_eq!(grid.get(Coord(3, 0)), None);
+        assert_eq!(unsafe { grid.at_coord_unchecked(Coord(2, 1)) }, 7);
+        grid.zero_fill();
+        assert_eq!(grid.at(2, 1), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_at_checks_both_axes() {
+        let grid: Grid = Grid::new(3, 2);
+        grid.at(3, 0);
+    }
+
+    #[test]
+    fn test_resolve_bounded() {
+        let grid: Grid = Grid::new(10, 5);
+        assert_eq!(grid.resolve(Coord(9, 4)), Some(Coord(9, 4)));
+        assert_eq!(grid.resolve(Coord(10, 4)), None);
+        assert_eq!(grid.resolve(Coord(0, -1)), None);
+        assert!(grid.is_border_at(Coord(0, 2)));
+    }
+
+    #[test]
+    fn test_resolve_torus() {
+        let grid: Grid = Grid::with_topology(10, 5, Topology::Torus);
+        assert_eq!(grid.resolve(Coord(10, 4)), Some(Coord(0, 4)));
+        assert_eq!(grid.resolve(Coord(-1, -1)), Some(Coord(9, 4)));
+        assert_eq!(grid.resolve(Coord(3, 12)), Some(Coord(3, 2)));
+        assert!(!grid.is_border_at(Coord(0, 2)));
+    }
+
+    #[test]
+    fn test_displacement_torus() {
+        let grid: Grid = Grid::with_topology(10, 10, Topology::Torus);
+        assert_eq!(grid.displacement(Coord(9, 0), Coord(0, 0)), Coord(1, 0));
+        assert_eq!(grid.displacement(Coord(0, 1), Coord(9, 8)), Coord(-1, -3));
+        assert_eq!(Grid::<u16>::new(10, 10).displacement(Coord(9, 0), Coord(0, 0)), Coord(-9, 0));
+    }
+
+    #[test]
+    fn test_neighborhood_wraps_in_torus() {
+        let grid: Grid = Grid::with_topology(10, 10, Topology::Torus);
+        let mut visited = Vec::new();
+        grid.apply_neighborhood_to_f(Coord(0, 0), Neighborhood::new(NeighborhoodShape::Moore, 1), |c| visited.push(c));
+        assert_eq!(visited.len(), 9);
+        assert!(visited.contains(&Coord(9, 9)));
+
+        let mut count = 0;
+        Grid::<u16>::new(10, 10).apply_neighborhood_to_f(Coord(0, 0), Neighborhood::new(NeighborhoodShape::Moore, 1), |_| count += 1);
+        assert_eq!(count, 4);
+    }
+
+    #[test]
+    fn test_neighborhood_shapes() {
+        let grid: Grid = Grid::new(20, 20);
+        let count = |shape| {
+            let mut count = 0;
+            grid.apply_neighborhood_to_f(Coord(10, 10), Neighborhood::new(shape, 2), |_| count += 1);
+            count
+        };
+        assert_eq!(count(NeighborhoodShape::VonNeumann), 13);
+        assert_eq!(count(NeighborhoodShape::Moore), 25);
+        assert_eq!(count(NeighborhoodShape::Euclidean), 13);
+
+        for shape in [NeighborhoodShape::VonNeumann, NeighborhoodShape::Moore, NeighborhoodShape::Euclidean] {
+            for radius in 0..6 {
+                let neighborhood = Neighborhood::new(shape, radius);
+                for dy in -radius..=radius {
+                    let w = neighborhood.half_width(dy);
+                    for dx in -radius..=radius {
+                        assert_eq!(neighborhood.contains(Coord(dx, dy)), dx.abs() <= w);
+                    }
+                }
+                for location in [Coord(0, 0), Coord(10, 10), Coord(19, 2)] {
+                    let mut count = 0;
+                    grid.apply_neighborhood_to_f(location, neighborhood, |_| count += 1);
+                    assert_eq!(grid.neighborhood_size(location, neighborhood), count);
+                }
+            }
+        }
+
+        let circle = Neighborhood::new(NeighborhoodShape::Euclidean, 3);
+        assert!(circle.contains(Coord(2, 2)));
+        assert!(!circle.contains(Coord(3, 1)));
+        assert!(!circle.contains(Coord(3, 3)));
+    }
+}
+//</editor-fold>
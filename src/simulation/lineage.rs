@@ -0,0 +1,214 @@
+use std::collections::{HashMap, HashSet};
+use serde::Serialize;
+
+/// Globally unique identifier of an individual. Unlike `Individual::index`, which is the slot of the
+/// individual in the population and is reused every generation, ids are never reused within a run.
+pub type IndividualId = u64;
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct LineageRecord {
+    pub id: IndividualId,
+    pub generation: u32,
+    // Empty for founders, i.e. individuals with a random genome.
+    pub parents: Vec<IndividualId>,
+}
+
+/// Parent records of every individual that is still relevant to the living population.
+///
+/// A child has two parents, so the full ancestry is a graph rather than a tree. The tree exports and
+/// the statistics follow the first parent of every individual, the same way mitochondrial lineages
+/// are traced in real populations.
+pub struct Lineage {
+    records: HashMap<IndividualId, LineageRecord>,
+}
+
+impl Lineage {
+    pub fn new() -> Lineage {
+        return Lineage { records: HashMap::new() };
+    }
+
+    pub fn record_birth(&mut self, id: IndividualId, generation: u32, parents: &[IndividualId]) {
+        self.records.insert(id, LineageRecord { id, generation, parents: parents.to_vec() });
+    }
+
+    pub fn get(&self, id: IndividualId) -> Option<&LineageRecord> {
+        return self.records.get(&id);
+    }
+
+    pub fn len(&self) -> usize {
+        return self.records.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.records.is_empty();
+    }
+
+    /// The individual followed by its first parent, its first grandparent, and so on up to its founder.
+    pub fn first_parent_line(&self, id: IndividualId) -> Vec<IndividualId> {
+        let mut line = vec![id];
+        let mut current = self.records.get(&id);
+        while let Some(record) = current {
+            match record.parents.first() {
+                Some(parent) => {
+                    line.push(*parent);
+                    current = self.records.get(parent);
+                }
+                None => break,
+            }
+        }
+        return line;
+    }
+
+    /// Number of distinct founders that still have first-parent descendants among `ids`.
+    pub fn surviving_founder_lineages(&self, ids: &[IndividualId]) -> usize {
+        let founders: HashSet<IndividualId> = ids.iter()
+            .map(|id| *self.first_parent_line(*id).last().unwrap())
+            .collect();
+        return founders.len();
+    }
+
+    /// The most recent first-parent ancestor shared by all of `ids`, if any.
+    pub fn most_recent_common_ancestor(&self, ids: &[IndividualId]) -> Option<IndividualId> {
+        let lines: Vec<Vec<IndividualId>> = ids.iter().map(|id| self.first_parent_line(*id)).collect();
+        let mut counts: HashMap<IndividualId, usize> = HashMap::new();
+        for line in lines.iter() {
+            for ancestor in line {
+                *counts.entry(*ancestor).or_default() += 1;
+            }
+        }
+        // Lines are ordered from the individual to its founder, so the first shared ancestor is the most recent.
+        return lines.first()?.iter().find(|a| counts[a] == ids.len()).copied();
+    }
+
+    /// Generations elapsed between the most recent common ancestor of `ids` and `current_generation`.
+    pub fn time_to_most_recent_common_ancestor(&self, ids: &[IndividualId], current_generation: u32) -> Option<u32> {
+        let ancestor = self.most_recent_common_ancestor(ids)?;
+        let generation = self.records.get(&ancestor)?.generation;
+        return Some(current_generation.saturating_sub(generation));
+    }
+
+    /// Forgets every individual that isn't an ancestor, through any parent, of `living`.
+    pub fn prune(&mut self, living: &[IndividualId]) {
+        let mut keep: HashSet<IndividualId> = HashSet::new();
+        let mut pending: Vec<IndividualId> = living.to_vec();
+        while let Some(id) = pending.pop() {
+            if !keep.insert(id) {
+                continue;
+            }
+            if let Some(record) = self.records.get(&id) {
+                pending.extend(record.parents.iter());
+            }
+        }
+        self.records.retain(|id, _| keep.contains(id));
+    }
+
+    /// Exports the first-parent ancestry of `leaves` in Newick format. Branch lengths are measured in
+    /// generations, and ancestors with a single descendant line are collapsed into the branch.
+    pub fn to_newick(&self, leaves: &[IndividualId]) -> String {
+        let mut children: HashMap<IndividualId, Vec<IndividualId>> = HashMap::new();
+        let mut roots: Vec<IndividualId> = Vec::new();
+        let mut visited: HashSet<IndividualId> = HashSet::new();
+        for leaf in leaves {
+            let line = self.first_parent_line(*leaf);
+            for pair in line.windows(2) {
+                if visited.insert(pair[0]) {
+                    children.entry(pair[1]).or_default().push(pair[0]);
+                }
+            }
+            let founder = *line.last().unwrap();
+            if visited.insert(founder) {
+                roots.push(founder);
+            }
+        }
+
+        let leaf_set: HashSet<IndividualId> = leaves.iter().copied().collect();
+        let subtrees: Vec<String> = roots.iter()
+            .map(|root| {
+                let founder_generation = self.records.get(root).map_or(0, |r| r.generation);
+                self.newick_subtree(*root, founder_generation, &children, &leaf_set)
+            })
+            .collect();
+        return format!("({});", subtrees.join(","));
+    }
+
+    fn newick_subtree(&self, id: IndividualId, parent_generation: u32,
+                      children: &HashMap<IndividualId, Vec<IndividualId>>, leaves: &HashSet<IndividualId>) -> String {
+        // Skip over the ancestors that only lead to a single child
+        let mut node = id;
+        while !leaves.contains(&node) && children.get(&node).is_some_and(|c| c.len() == 1) {
+            node = children[&node][0];
+        }
+
+        let generation = self.records.get(&node).map_or(0, |r| r.generation);
+        let mut newick = String::new();
+        if let Some(node_children) = children.get(&node) {
+            let subtrees: Vec<String> = node_children.iter()
+                .map(|child| self.newick_subtree(*child, generation, children, leaves))
+                .collect();
+            newick.push_str(&format!("({})", subtrees.join(",")));
+        }
+        newick.push_str(&format!("{}:{}", node, generation.saturating_sub(parent_generation)));
+        return newick;
+    }
+
+    /// Exports every record, sorted by id, as a JSON array.
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        let mut records: Vec<&LineageRecord> = self.records.values().collect();
+        records.sort_by_key(|r| r.id);
+        return serde_json::to_string_pretty(&records);
+    }
+}
+
+impl Default for Lineage {
+    fn default() -> Self {
+        Lineage::new()
+    }
+}
+
+//<editor-fold desc="Unit tests">
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Two founders (1, 2). 3 and 4 descend from 1, 5 from 2; then 6 and 7 descend from 3.
+    fn make_lineage() -> Lineage {
+        let mut lineage = Lineage::new();
+        lineage.record_birth(1, 0, &[]);
+        lineage.record_birth(2, 0, &[]);
+        lineage.record_birth(3, 1, &[1, 2]);
+        lineage.record_birth(4, 1, &[1, 1]);
+        lineage.record_birth(5, 1, &[2, 1]);
+        lineage.record_birth(6, 2, &[3, 5]);
+        lineage.record_birth(7, 2, &[3, 4]);
+        return lineage;
+    }
+
+    #[test]
+    fn test_common_ancestor() {
+        let lineage = make_lineage();
+        assert_eq!(lineage.first_parent_line(6), vec![6, 3, 1]);
+        assert_eq!(lineage.most_recent_common_ancestor(&[6, 7]), Some(3));
+        assert_eq!(lineage.time_to_most_recent_common_ancestor(&[6, 7], 2), Some(1));
+        assert_eq!(lineage.most_recent_common_ancestor(&[6, 5]), None);
+        assert_eq!(lineage.surviving_founder_lineages(&[6, 7]), 1);
+        assert_eq!(lineage.surviving_founder_lineages(&[6, 5]), 2);
+    }
+
+    #[test]
+    fn test_prune_keeps_both_parents() {
+        let mut lineage = make_lineage();
+        lineage.prune(&[6]);
+        // 4 isn't an ancestor of 6
+        assert_eq!(lineage.len(), 5);
+        assert!(lineage.get(4).is_none());
+        assert!(lineage.get(5).is_some());
+    }
+
+    #[test]
+    fn test_newick() {
+        let lineage = make_lineage();
+        assert_eq!(lineage.to_newick(&[6, 7]), "((6:1,7:1)3:1);");
+        assert_eq!(lineage.to_newick(&[6, 5]), "(6:2,5:1);");
+    }
+}
+//</editor-fold>
@@ -0,0 +1,145 @@
+use std::error::Error;
+use std::path::Path;
+use crate::render::frame::Frame;
+use crate::simulation::types::Coord;
+
+/// Layout of a world, read from a text or PNG file, whose size gives the size of the world. The
+/// first line or row of pixels is the north edge. In text files, each character is a cell:
+///
+/// | Character | Pixel color | Cell                                                        |
+/// |-----------|-------------|-------------------------------------------------------------|
+/// | `#`       | black       | barrier                                                     |
+/// | `S`       | blue        | spawn zone, where the generations start                     |
+/// | `F`       | green       | fertile, with the `map` food pattern                        |
+/// | `Z`       | red         | survival zone, which becomes the mask of the mask challenge |
+/// | other     | other       | empty                                                       |
+///
+/// Shorter lines are padded with empty cells.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct WorldMap {
+    pub width: u16,
+    pub height: u16,
+    pub barriers: Vec<Coord>,
+    pub spawn_zone: Vec<Coord>,
+    pub food_zone: Vec<Coord>,
+    pub survival_zone: Vec<Coord>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MapCell {
+    Empty,
+    Barrier,
+    Spawn,
+    Food,
+    Survival,
+}
+
+impl WorldMap {
+    /// Reads a PNG image if the file name ends with `.png`, and a text file otherwise.
+    pub fn read_from_file(path: &str) -> Result<WorldMap, Box<dyn Error>> {
+        let map = if path.to_lowercase().ends_with(".png") {
+            let image = Frame::read_png(Path::new(path))?;
+            let rows: Vec<Vec<MapCell>> = (0..image.height)
+                .map(|y| (0..image.width).map(|x| WorldMap::cell_of_color(image.pixel(x, y))).collect())
+                .collect();
+            WorldMap::from_rows(&rows)?
+        } else {
+            WorldMap::from_text(&std::fs::read_to_string(path)?)?
+        };
+        return Ok(map);
+    }
+
+    pub fn from_text(text: &str) -> Result<WorldMap, Box<dyn Error>> {
+        let rows: Vec<Vec<MapCell>> = text.lines()
+            .map(|line| line.chars().map(WorldMap::cell_of_char).collect())
+            .collect();
+        return WorldMap::from_rows(&rows);
+    }
+
+    fn cell_of_char(character: char) -> MapCell {
+        match character {
+            '#' => MapCell::Barrier,
+            'S' => MapCell::Spawn,
+            'F' => MapCell::Food,
+            'Z' => MapCell::Survival,
+            _ => MapCell::Empty,
+        }
+    }
+
+    // Only clear colors count, so that anti-aliased or off-white pixels are empty
+    fn cell_of_color(color: [u8; 3]) -> MapCell {
+        let [r, g, b] = color.map(|channel| channel >= 128);
+        if color.iter().all(|&channel| channel < 64) {
+            return MapCell::Barrier;
+        }
+        match (r, g, b) {
+            (false, false, true) => MapCell::Spawn,
+            (false, true, false) => MapCell::Food,
+            (true, false, false) => MapCell::Survival,
+            _ => MapCell::Empty,
+        }
+    }
+
+    fn from_rows(rows: &[Vec<MapCell>]) -> Result<WorldMap, Box<dyn Error>> {
+        let width = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+        let height = rows.len();
+        if width == 0 || width > i16::MAX as usize || height > i16::MAX as usize {
+            return Err(format!("A map must have between 1 and {} cells on each side", i16::MAX).into());
+        }
+
+        let mut map = WorldMap { width: width as u16, height: height as u16, ..WorldMap::default() };
+        for (row_number, row) in rows.iter().enumerate() {
+            let y = (height - 1 - row_number) as i16;
+            for (x, cell) in row.iter().enumerate() {
+                let location = Coord(x as i16, y);
+                match cell {
+                    MapCell::Empty => {}
+                    MapCell::Barrier => map.barriers.push(location),
+                    MapCell::Spawn => map.spawn_zone.push(location),
+                    MapCell::Food => map.food_zone.push(location),
+                    MapCell::Survival => map.survival_zone.push(location),
+                }
+            }
+        }
+        return Ok(map);
+    }
+}
+
+//<editor-fold desc="Unit tests">
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_text_map() {
+        let map = WorldMap::from_text("#####\n#S.F\n#ZZ..#\n").unwrap();
+        assert_eq!((map.width, map.height), (6, 3));
+        // The first line is the north edge
+        assert!(map.barriers.contains(&Coord(0, 2)) && map.barriers.contains(&Coord(5, 0)));
+        assert_eq!(map.barriers.len(), 8);
+        assert_eq!(map.spawn_zone, vec![Coord(1, 1)]);
+        assert_eq!(map.food_zone, vec![Coord(3, 1)]);
+        assert_eq!(map.survival_zone, vec![Coord(1, 0), Coord(2, 0)]);
+        assert!(WorldMap::from_text("").is_err());
+    }
+
+    #[test]
+    fn test_image_map() {
+        let mut frame = Frame::new(3, 2, 1);
+        frame.fill_cell(Coord(0, 1), [0, 0, 0]);
+        frame.fill_cell(Coord(1, 1), [20, 30, 230]);
+        frame.fill_cell(Coord(2, 0), [200, 10, 10]);
+        frame.fill_cell(Coord(0, 0), [128, 128, 128]);
+        let path = std::env::temp_dir().join(format!("biosim-map-{}.png", std::process::id()));
+        frame.write_png(&path).unwrap();
+        let map = WorldMap::read_from_file(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!((map.width, map.height), (3, 2));
+        assert_eq!(map.barriers, vec![Coord(0, 1)]);
+        assert_eq!(map.spawn_zone, vec![Coord(1, 1)]);
+        assert_eq!(map.survival_zone, vec![Coord(2, 0)]);
+        assert!(map.food_zone.is_empty());
+    }
+}
+//</editor-fold>
@@ -2,15 +2,23 @@ use rand::Rng;
 
 pub mod types;
 pub mod parameters;
+pub mod food;
 pub mod grid;
+pub mod lineage;
+pub mod map;
 pub mod peeps;
+pub mod queues;
+pub mod random;
+#[allow(clippy::module_inception)]
 pub mod simulation;
+pub mod replay;
 pub mod signals;
+pub mod statistics;
 pub mod world;
 
 // Generates a random number, and returns true if it falls within the probability
 pub fn probability_to_bool(probability: f32) -> bool {
-    let mut rng = rand::thread_rng();
+    let mut rng = random::rng();
     let random_number = rng.gen_range(0.0..1.0f32);
     random_number < probability
 }
\ No newline at end of file
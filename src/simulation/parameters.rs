@@ -4,12 +4,26 @@ use std::error::Error;
 use std::fs::File;
 use std::io::BufReader;
 use serde::{Serialize, Deserialize};
+use crate::population::brain::activation::Activation;
+use crate::population::genome::crossover::CrossoverStrategy;
+use crate::population::genome::gene::MAX_NODE_NUMBER;
+use crate::population::individual::MAX_POPULATION;
+use crate::population::species::{Species, SpeciesId};
+use crate::render::frame::{Frame, ImageFormat};
+use crate::simulation::food::FoodPattern;
+use crate::simulation::grid::{NeighborhoodShape, Topology};
+use crate::simulation::map::WorldMap;
+use crate::simulation::peeps::survival_criteria::Challenges;
+use crate::simulation::peeps::survival_criteria::mask::SurvivalMask;
+use crate::simulation::peeps::survival_criteria::region::Region;
+use crate::simulation::queues::CollisionPolicy;
+use crate::simulation::simulation::{GenerationMode, MigrationTopology};
 
 //<editor-fold desc="Parameter struct">
 // To add a parameter, add it to the `Parameters` struct below.
 // Then, add a function returning its default value to the `parameter_defaults` module.
 // Finally, use the serde default attribute to point to the function.
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Parameters {
     #[serde(default = "parameter_defaults::size_x")]
     pub size_x: u16,
@@ -17,12 +31,81 @@ pub struct Parameters {
     #[serde(default = "parameter_defaults::size_y")]
     pub size_y: u16,
 
+    #[serde(default = "parameter_defaults::topology")]
+    pub topology: Topology,
+
+    // Text or PNG file with the barriers and zones of the world, see [`WorldMap`]. It sets the
+    // size of the world, instead of size_x and size_y.
+    #[serde(default = "parameter_defaults::map_file")]
+    pub map_file: Option<String>,
+
+    // The map of `map_file`, loaded along with the parameters
+    #[serde(skip)]
+    pub map: Option<WorldMap>,
+
     #[serde(default = "parameter_defaults::population")]
-    pub population: u16,
+    pub population: u32,
 
     #[serde(default = "parameter_defaults::steps_per_generation")]
     pub steps_per_generation: u16,
 
+    #[serde(default = "parameter_defaults::generation_mode")]
+    pub generation_mode: GenerationMode,
+
+    // The following parameters only apply to the continuous generation mode.
+    // Age, in steps, at which an individual dies
+    #[serde(default = "parameter_defaults::max_age")]
+    pub max_age: u32,
+
+    #[serde(default = "parameter_defaults::initial_energy")]
+    pub initial_energy: f32,
+
+    // Energy every individual spends at each step
+    #[serde(default = "parameter_defaults::energy_per_step")]
+    pub energy_per_step: f32,
+
+    // Energy gained at each step by the individuals that meet the survival challenge
+    #[serde(default = "parameter_defaults::challenge_energy_gain")]
+    pub challenge_energy_gain: f32,
+
+    // Energy a parent spends on a child, which the child starts with
+    #[serde(default = "parameter_defaults::reproduction_energy")]
+    pub reproduction_energy: f32,
+
+    // Children are born at most this many cells away from their parent on each axis. Also used by
+    // the local mating of the discrete mode.
+    #[serde(default = "parameter_defaults::birth_radius")]
+    pub birth_radius: u16,
+
+    // Number of populations evolving side by side, each of `population` individuals in its own world
+    #[serde(default = "parameter_defaults::island_count")]
+    pub island_count: u16,
+
+    // Challenge of each island, in order. The islands without one use `challenge`.
+    #[serde(default = "parameter_defaults::island_challenges")]
+    pub island_challenges: Vec<Challenges>,
+
+    // Map of each island, in order, with the barriers, spawn zone and food zone of that island.
+    // The islands without one use `map_file`. They must have the size of the world, and can't
+    // have a survival zone: the mask challenge uses the same mask on every island.
+    #[serde(default = "parameter_defaults::island_map_files")]
+    pub island_map_files: Vec<String>,
+
+    // The maps of `island_map_files`, loaded along with the parameters
+    #[serde(skip)]
+    pub island_maps: Vec<WorldMap>,
+
+    #[serde(default = "parameter_defaults::migration_topology")]
+    pub migration_topology: MigrationTopology,
+
+    // Generations between two migrations, 0 to keep the islands isolated
+    #[serde(default = "parameter_defaults::migration_interval")]
+    pub migration_interval: u32,
+
+    // Genomes each island sends to the others at every migration
+    #[serde(default = "parameter_defaults::migrants")]
+    pub migrants: u32,
+
     #[serde(default = "parameter_defaults::max_generations")]
     pub max_generations: u32,
 
@@ -47,18 +130,126 @@ pub struct Parameters {
     #[serde(default = "parameter_defaults::delete_ration")]
     pub delete_ration: f64,
 
+    // Probability, per gene, of adding Gaussian noise to the weight
+    #[serde(default = "parameter_defaults::weight_perturbation_rate")]
+    pub weight_perturbation_rate: f64,
+
+    // Standard deviation of that noise, in the units of the float weight (about -4.0..4.0)
+    #[serde(default = "parameter_defaults::weight_perturbation_stddev")]
+    pub weight_perturbation_stddev: f32,
+
+    // Probability, per gene, of flipping a bit of the weight
+    #[serde(default = "parameter_defaults::weight_bit_flip_rate")]
+    pub weight_bit_flip_rate: f64,
+
+    // Probability, per gene, of connecting the source or the sink to a new random node
+    #[serde(default = "parameter_defaults::rewiring_rate")]
+    pub rewiring_rate: f64,
+
+    // Probability, per genome, of copying a gene next to itself
+    #[serde(default = "parameter_defaults::gene_duplication_rate")]
+    pub gene_duplication_rate: f64,
+
+    // Probability, per genome, of reversing the order of a run of genes
+    #[serde(default = "parameter_defaults::segment_inversion_rate")]
+    pub segment_inversion_rate: f64,
+
+    // Gives each genome its own point mutation and insertion/deletion rates, which mutate along
+    // with it, instead of using the rates above
+    #[serde(default = "parameter_defaults::self_adaptive_mutation_rates")]
+    pub self_adaptive_mutation_rates: bool,
+
+    // How fast the evolved rates change: each generation they are multiplied by exp(rate * N(0, 1))
+    #[serde(default = "parameter_defaults::mutation_rate_learning_rate")]
+    pub mutation_rate_learning_rate: f64,
+
+    #[serde(default = "parameter_defaults::crossover")]
+    pub crossover: CrossoverStrategy,
+
+    // Activation function of the neurons
+    #[serde(default = "parameter_defaults::activation")]
+    pub activation: Activation,
+
+    // Lets each genome carry the activation of every neuron, instead of using `activation` for all
+    #[serde(default = "parameter_defaults::evolve_activations")]
+    pub evolve_activations: bool,
+
     #[serde(default = "parameter_defaults::sexual_reproduction")]
     pub sexual_reproduction: bool,
 
+    // When set, parents only mate with the survivors within this radius of their location, and the
+    // children are born within `birth_radius` of them. Mates are drawn from the whole population
+    // otherwise.
+    #[serde(default = "parameter_defaults::mating_radius")]
+    pub mating_radius: Option<u16>,
+
+    // Lets the individuals kill the one in front of them through the kill forward action
     #[serde(default = "parameter_defaults::kill_enabled")]
     pub kill_enabled: bool,
 
     #[serde(default = "parameter_defaults::choose_parents_by_fitness")]
     pub choose_parents_by_fitness: bool,
 
+    // Challenge the individuals must meet to survive a generation
+    #[serde(default = "parameter_defaults::challenge")]
+    pub challenge: Challenges,
+
+    // Groups of individuals that only breed among themselves. Everybody belongs to a single species
+    // when empty.
+    #[serde(default = "parameter_defaults::species")]
+    pub species: Vec<Species>,
+
+    // Region the individuals must end the generation in, for the region challenge
+    #[serde(default = "parameter_defaults::challenge_region")]
+    pub challenge_region: Option<Region>,
+
+    // Grayscale PNG image with the survival probability of each cell, for the mask challenge. The
+    // survival zone of the map is used when missing.
+    #[serde(default = "parameter_defaults::challenge_mask")]
+    pub challenge_mask: Option<String>,
+
+    // The image of `challenge_mask`, loaded along with the parameters
+    #[serde(skip)]
+    pub survival_mask: Option<SurvivalMask>,
+
+    // Energy needed to pass the energy challenge
+    #[serde(default = "parameter_defaults::energy_threshold")]
+    pub energy_threshold: f32,
+
+    #[serde(default = "parameter_defaults::food_pattern")]
+    pub food_pattern: FoodPattern,
+
+    // Food added to each fertile cell at every step
+    #[serde(default = "parameter_defaults::food_growth_rate")]
+    pub food_growth_rate: f32,
+
+    #[serde(default = "parameter_defaults::food_max_per_cell")]
+    pub food_max_per_cell: f32,
+
+    // Food an individual takes from its cell, and the energy it gains, when it eats
+    #[serde(default = "parameter_defaults::food_per_bite")]
+    pub food_per_bite: f32,
+
+    #[serde(default = "parameter_defaults::food_patch_count")]
+    pub food_patch_count: u16,
+
+    #[serde(default = "parameter_defaults::food_patch_radius")]
+    pub food_patch_radius: u16,
+
+    // How far the food sensors see
+    #[serde(default = "parameter_defaults::food_sensor_radius")]
+    pub food_sensor_radius: i16,
+
+    // Energy spent on every step an individual moves
+    #[serde(default = "parameter_defaults::move_energy_cost")]
+    pub move_energy_cost: f32,
+
     #[serde(default = "parameter_defaults::population_sensor_radius")]
     pub population_sensor_radius: i16,
 
+    #[serde(default = "parameter_defaults::neighborhood_shape")]
+    pub neighborhood_shape: NeighborhoodShape,
+
     #[serde(default = "parameter_defaults::signal_sensor_radius")]
     pub signal_sensor_radius: u16,
 
@@ -76,15 +267,148 @@ pub struct Parameters {
 
     #[serde(default = "parameter_defaults::valence_saturation_magnitude")]
     pub valence_saturation_magnitude: f32,
+
+    #[serde(default = "parameter_defaults::output_directory")]
+    pub output_directory: String,
+
+    #[serde(default = "parameter_defaults::save_video")]
+    pub save_video: bool,
+
+    #[serde(default = "parameter_defaults::save_frames")]
+    pub save_frames: bool,
+
+    #[serde(default = "parameter_defaults::video_stride")]
+    pub video_stride: u32,
+
+    #[serde(default = "parameter_defaults::video_save_first_frames")]
+    pub video_save_first_frames: u32,
+
+    #[serde(default = "parameter_defaults::display_scale")]
+    pub display_scale: u32,
+
+    #[serde(default = "parameter_defaults::image_format")]
+    pub image_format: ImageFormat,
+
+    #[serde(default = "parameter_defaults::save_replay")]
+    pub save_replay: bool,
+
+    #[serde(default = "parameter_defaults::track_lineage")]
+    pub track_lineage: bool,
+
+    #[serde(default = "parameter_defaults::collision_policy")]
+    pub collision_policy: CollisionPolicy,
+
+    // Seeds the simulation's random number generator, so that runs can be reproduced. A random
+    // seed is used when missing.
+    #[serde(default = "parameter_defaults::seed")]
+    pub seed: Option<u64>,
 }
 //</editor-fold>
 
 impl Parameters {
     pub fn read_from_reader(reader: &mut BufReader<File>) -> Result<Parameters, Box<dyn Error>> {
-        let parameters: Parameters = serde_yaml::from_reader(reader)?;
+        let mut parameters: Parameters = serde_yaml::from_reader(reader)?;
+        if let Some(path) = &parameters.map_file {
+            let map = WorldMap::read_from_file(path).map_err(|e| format!("Can't read map_file {}: {}", path, e))?;
+            parameters.size_x = map.width;
+            parameters.size_y = map.height;
+            if !map.survival_zone.is_empty() {
+                parameters.survival_mask = Some(SurvivalMask::from_zone(map.width, map.height, &map.survival_zone));
+            }
+            parameters.map = Some(map);
+        }
+        for path in &parameters.island_map_files {
+            let map = WorldMap::read_from_file(path).map_err(|e| format!("Can't read island map {}: {}", path, e))?;
+            parameters.island_maps.push(map);
+        }
+        if let Some(path) = &parameters.challenge_mask {
+            let mask = SurvivalMask::read_png(path, parameters.size_x, parameters.size_y)
+                .map_err(|e| format!("Can't read challenge_mask {}: {}", path, e))?;
+            parameters.survival_mask = Some(mask);
+        }
+        parameters.validate()?;
         Ok(parameters)
     }
 
+    // Rejects the values the simulation can't represent, instead of letting them wrap around.
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if self.max_number_neurons == 0 || self.max_number_neurons as u32 > MAX_NODE_NUMBER as u32 + 1 {
+            return Err(format!("max_number_neurons must be between 1 and {}", MAX_NODE_NUMBER as u32 + 1).into());
+        }
+        // Locations are stored as 16-bit signed coordinates
+        if self.size_x == 0 || self.size_y == 0 || self.size_x > i16::MAX as u16 || self.size_y > i16::MAX as u16 {
+            return Err(format!("size_x and size_y must be between 1 and {}", i16::MAX).into());
+        }
+        if self.island_maps.iter().any(|map| map.width != self.size_x || map.height != self.size_y || !map.survival_zone.is_empty()) {
+            return Err("The island maps must have the size of the world, and no survival zone".into());
+        }
+        // Every individual needs a cell of its own, on every island
+        let barriers = (0..self.island_count as usize)
+            .filter_map(|i| self.island_map(i))
+            .map(|map| map.barriers.len() as u64)
+            .max()
+            .unwrap_or(0);
+        let cells = self.size_x as u64 * self.size_y as u64 - barriers;
+        if self.population == 0 || self.population > MAX_POPULATION || self.population as u64 >= cells {
+            return Err(format!("population must be between 1 and {}, and smaller than the number of cells",
+                               MAX_POPULATION).into());
+        }
+        // A generation would never end
+        if self.steps_per_generation == 0 {
+            return Err("steps_per_generation must be at least 1".into());
+        }
+        // Frames are also rendered from replays, so the scale is checked even when nothing is recorded
+        if self.display_scale == 0 {
+            return Err("display_scale must be at least 1".into());
+        }
+        if self.save_video || self.save_frames {
+            Frame::check_size(self.size_x, self.size_y, self.display_scale)?;
+        }
+        if self.generation_mode == GenerationMode::Continuous && self.reproduction_energy <= 0.0 {
+            return Err("reproduction_energy must be positive in the continuous generation mode".into());
+        }
+        if self.birth_radius == 0 || self.birth_radius > i16::MAX as u16 {
+            return Err(format!("birth_radius must be between 1 and {}", i16::MAX).into());
+        }
+        let islands = self.island_count as usize;
+        if islands == 0 || self.island_challenges.len() > islands || self.island_maps.len() > islands {
+            return Err("island_count must be positive, and island_challenges and island_map_files can't have more entries".into());
+        }
+        if !self.species.is_empty() {
+            let population: u64 = self.species.iter().map(|s| s.population as u64).sum();
+            if population != self.population as u64 || self.species.len() > SpeciesId::MAX as usize + 1 {
+                return Err(format!("The populations of the species must add up to population, with at most {} species",
+                                   SpeciesId::MAX as usize + 1).into());
+            }
+        }
+        if self.uses_challenge(Challenges::Region) && self.challenge_region.is_none() {
+            return Err("The region challenge needs a challenge_region".into());
+        }
+        if self.uses_challenge(Challenges::Mask) && self.survival_mask.is_none() {
+            return Err("The mask challenge needs a challenge_mask".into());
+        }
+        if self.migrants > self.population {
+            return Err("migrants can't be more than the population of an island".into());
+        }
+        if self.mating_radius.is_some_and(|radius| radius > i16::MAX as u16) {
+            return Err(format!("mating_radius must be at most {}", i16::MAX).into());
+        }
+        return Ok(());
+    }
+
+    // The map of the given island, if it has one
+    pub fn island_map(&self, island: usize) -> Option<&WorldMap> {
+        return self.island_maps.get(island).or(self.map.as_ref());
+    }
+
+    // Whether some island or species faces `challenge`
+    fn uses_challenge(&self, challenge: Challenges) -> bool {
+        let islands = (0..self.island_count as usize)
+            .map(|i| self.island_challenges.get(i).copied().unwrap_or(self.challenge));
+        let species = self.species.iter().filter_map(|s| s.challenge);
+        return islands.chain(species).any(|c| c == challenge);
+    }
+
     pub fn read_from_file(file_name: &str) -> Result<Parameters, Box<dyn Error>> {
         let file = File::open(file_name)?;
         let mut reader = BufReader::new(file);
@@ -121,8 +445,56 @@ mod test {
         let params : Parameters = serde_yaml::from_str("default: true").unwrap();
         assert_eq!(params.size_y, size_y());
         assert_eq!(params.kill_enabled, kill_enabled());
-        assert_eq!(params.population, 100);
+        assert_eq!(params.population, 600);
+
+    }
+
+    #[test]
+    fn test_validate_neuron_count() {
+        let mut params = Parameters::defaults();
+        params.max_number_neurons = 300;
+        assert!(params.validate().is_ok());
+        params.max_number_neurons = 40000;
+        assert!(params.validate().is_err());
+        params.max_number_neurons = 0;
+        assert!(params.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_large_population() {
+        let mut params = Parameters::defaults();
+        params.size_x = 1000;
+        params.size_y = 1000;
+        params.population = 300_000;
+        assert!(params.validate().is_ok());
+        params.population = 1_000_000;
+        assert!(params.validate().is_err());
+        params.size_x = 40000;
+        params.population = 10;
+        assert!(params.validate().is_err());
+    }
 
+    #[test]
+    fn test_validate_steps_per_generation() {
+        let mut params = Parameters::defaults();
+        params.steps_per_generation = 0;
+        assert!(params.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_display_scale() {
+        let mut params = Parameters::defaults();
+        params.display_scale = 0;
+        assert!(params.validate().is_err());
+        // A large world is fine until its frames are recorded
+        params.display_scale = 8;
+        params.size_x = 30000;
+        params.size_y = 30000;
+        params.save_video = false;
+        params.save_frames = false;
+        assert!(params.validate().is_ok());
+        params.save_video = true;
+        assert!(params.validate().is_err());
     }
 }
 //</editor-fold>
\ No newline at end of file
@@ -1,11 +1,52 @@
+use crate::population::brain::activation::Activation;
+use crate::population::genome::crossover::CrossoverStrategy;
+use crate::population::species::Species;
+use crate::render::frame::ImageFormat;
+use crate::simulation::food::FoodPattern;
+use crate::simulation::grid::{NeighborhoodShape, Topology};
+use crate::simulation::peeps::survival_criteria::Challenges;
+use crate::simulation::peeps::survival_criteria::region::Region;
+use crate::simulation::queues::CollisionPolicy;
+use crate::simulation::simulation::{GenerationMode, MigrationTopology};
+
 pub(super) fn size_x() -> u16 { 128 }
 
 pub(super) fn size_y() -> u16 { 128 }
 
-pub(super) fn population() -> u16 { 600 }
+pub(super) fn topology() -> Topology { Topology::Bounded }
+
+pub(super) fn map_file() -> Option<String> { None }
+
+pub(super) fn population() -> u32 { 600 }
 
 pub(super) fn steps_per_generation() -> u16 { 500 }
 
+pub(super) fn generation_mode() -> GenerationMode { GenerationMode::Discrete }
+
+pub(super) fn max_age() -> u32 { 300 }
+
+pub(super) fn initial_energy() -> f32 { 100.0 }
+
+pub(super) fn energy_per_step() -> f32 { 1.0 }
+
+pub(super) fn challenge_energy_gain() -> f32 { 2.0 }
+
+pub(super) fn reproduction_energy() -> f32 { 50.0 }
+
+pub(super) fn birth_radius() -> u16 { 2 }
+
+pub(super) fn island_count() -> u16 { 1 }
+
+pub(super) fn island_challenges() -> Vec<Challenges> { Vec::new() }
+
+pub(super) fn island_map_files() -> Vec<String> { Vec::new() }
+
+pub(super) fn migration_topology() -> MigrationTopology { MigrationTopology::Ring }
+
+pub(super) fn migration_interval() -> u32 { 10 }
+
+pub(super) fn migrants() -> u32 { 5 }
+
 pub(super) fn max_generations() -> u32 { 00 }
 
 pub(super) fn num_threads() -> u8 { 4 }
@@ -22,14 +63,66 @@ pub(super) fn gene_insertion_deletion_rate() -> f64 { 0.0001f64 }
 
 pub(super) fn delete_ration() -> f64 { 0.7f64 }
 
+pub(super) fn weight_perturbation_rate() -> f64 { 0.0f64 }
+
+pub(super) fn weight_perturbation_stddev() -> f32 { 0.1f32 }
+
+pub(super) fn weight_bit_flip_rate() -> f64 { 0.0f64 }
+
+pub(super) fn rewiring_rate() -> f64 { 0.0f64 }
+
+pub(super) fn gene_duplication_rate() -> f64 { 0.0f64 }
+
+pub(super) fn segment_inversion_rate() -> f64 { 0.0f64 }
+
+pub(super) fn self_adaptive_mutation_rates() -> bool { false }
+
+pub(super) fn mutation_rate_learning_rate() -> f64 { 0.2f64 }
+
+pub(super) fn crossover() -> CrossoverStrategy { CrossoverStrategy::SinglePoint }
+
+pub(super) fn activation() -> Activation { Activation::Tanh }
+
+pub(super) fn evolve_activations() -> bool { false }
+
 pub(super) fn sexual_reproduction() -> bool { true }
 
+pub(super) fn mating_radius() -> Option<u16> { None }
+
 pub(super) fn kill_enabled() -> bool { false }
 
 pub(super) fn choose_parents_by_fitness() -> bool { true }
 
+pub(super) fn challenge() -> Challenges { Challenges::Circle }
+
+pub(super) fn species() -> Vec<Species> { Vec::new() }
+
+pub(super) fn challenge_region() -> Option<Region> { None }
+
+pub(super) fn challenge_mask() -> Option<String> { None }
+
+pub(super) fn energy_threshold() -> f32 { 50.0 }
+
+pub(super) fn food_pattern() -> FoodPattern { FoodPattern::None }
+
+pub(super) fn food_growth_rate() -> f32 { 0.05 }
+
+pub(super) fn food_max_per_cell() -> f32 { 10.0 }
+
+pub(super) fn food_per_bite() -> f32 { 2.0 }
+
+pub(super) fn food_patch_count() -> u16 { 4 }
+
+pub(super) fn food_patch_radius() -> u16 { 8 }
+
+pub(super) fn food_sensor_radius() -> i16 { 3 }
+
+pub(super) fn move_energy_cost() -> f32 { 0.2 }
+
 pub(super) fn population_sensor_radius() -> i16 { 5 }
 
+pub(super) fn neighborhood_shape() -> NeighborhoodShape { NeighborhoodShape::Moore }
+
 pub(super) fn signal_sensor_radius() -> u16 { 1 }
 
 pub(super) fn responsiveness() -> f32 { 0.5 }
@@ -40,4 +133,26 @@ pub(super) fn long_probe_distance() -> u32 { 16 }
 
 pub(super) fn short_probe_distance() -> u32 { 4 }
 
-pub(super) fn valence_saturation_magnitude() -> f32 { 0.5 }
\ No newline at end of file
+pub(super) fn valence_saturation_magnitude() -> f32 { 0.5 }
+
+pub(super) fn output_directory() -> String { "output".to_string() }
+
+pub(super) fn save_video() -> bool { false }
+
+pub(super) fn save_frames() -> bool { false }
+
+pub(super) fn video_stride() -> u32 { 25 }
+
+pub(super) fn video_save_first_frames() -> u32 { 2 }
+
+pub(super) fn display_scale() -> u32 { 8 }
+
+pub(super) fn image_format() -> ImageFormat { ImageFormat::Png }
+
+pub(super) fn save_replay() -> bool { false }
+
+pub(super) fn track_lineage() -> bool { false }
+
+pub(super) fn collision_policy() -> CollisionPolicy { CollisionPolicy::IndexOrder }
+
+pub(super) fn seed() -> Option<u64> { None }
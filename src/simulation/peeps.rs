@@ -1,21 +1,40 @@
 pub mod survival_criteria;
 
-use std::collections::HashMap;
 use rand::Rng;
 use rand::seq::SliceRandom;
 use crate::Parameters;
-use crate::population::genome::{Genome, make_random_genome};
-use crate::population::genome::mutations::breed_from_parents;
-use crate::population::individual::Individual;
-use crate::simulation::grid::EMPTY_CELL;
-use crate::simulation::peeps::survival_criteria::{Challenges, get_challenge_function};
+use crate::population::genome::{Genome, make_founder_genome, make_random_genome};
+use crate::population::genome::mutations::{breed_from_parents, MutationCounts};
+use crate::population::individual::{Individual, IndividualIndex};
+use crate::population::species::species_at_index;
+use crate::simulation::peeps::survival_criteria::{ChallengeFunction, Challenges, get_challenge_function};
+use crate::simulation::lineage::{IndividualId, Lineage};
 use crate::simulation::probability_to_bool;
+use crate::simulation::food::Food;
+use crate::simulation::grid::{Grid, Neighborhood};
+use crate::simulation::map::WorldMap;
+use crate::simulation::queues::{BirthQueue, DeathQueue, EatQueue, KillQueue, MoveQueue, order_moves};
+use crate::simulation::random;
+use crate::simulation::replay::{ReplayEvent, ReplayLog};
 use crate::simulation::signals::Signals;
-use crate::simulation::types::Coord;
-use crate::simulation::world::World;
+use crate::simulation::simulation::GenerationMode;
+use crate::simulation::types::{Coord, Dir};
+use crate::simulation::world::{Cell, World};
 
-pub type MoveQueue = HashMap<u16, Vec<(f32, f32)>>;
-pub type DeathQueue = Vec<u16>;
+/// A survivor of a generation, with what breeding the next one takes from it.
+#[derive(Debug, Clone)]
+pub struct Parent {
+    pub id: IndividualId,
+    pub genome: Genome,
+    // Where it ended the generation, for the local mating
+    pub location: Coord,
+}
+
+impl From<&Individual> for Parent {
+    fn from(individual: &Individual) -> Parent {
+        return Parent { id: individual.id, genome: individual.genome.clone(), location: individual.location };
+    }
+}
 
 pub struct Peeps<'a> {
     pub world: World,
@@ -25,25 +44,76 @@ pub struct Peeps<'a> {
     // An individual can have multiple urges to move in a given direction. We need to keep track of them
     // and process them to get the overall direction of the movement urge.
     pub move_queue: MoveQueue,
+    // Scratch buffer with the moves of a step, as (index, urge) pairs
+    moves: Vec<(IndividualIndex, (f32, f32))>,
+    // Only used in the continuous generation mode
+    pub birth_queue: BirthQueue,
+    pub eat_queue: EatQueue,
+    pub kill_queue: KillQueue,
+    // Indices of the dead, which the children born in the continuous mode take over
+    free_indices: Vec<IndividualIndex>,
+    // Number of individuals removed through the death and kill queues during the current generation.
+    pub kills: u32,
+    // Births and natural deaths during the current period, in the continuous mode
+    pub births: u32,
+    pub deaths_of_age: u32,
+    pub starvations: u32,
+    // Period the births of the continuous mode are recorded in by the lineage
+    period: u32,
+    // Mutations of each kind that happened while breeding the current generation.
+    pub mutation_counts: MutationCounts,
+    pub replay: ReplayLog,
+    // Ancestry records, only kept when `track_lineage` is enabled.
+    pub lineage: Lineage,
+    next_id: IndividualId,
+    // Challenge of this population, which can differ from the parameters' on an island
+    pub challenge: Challenges,
     pub parameters: &'a Parameters
 }
 
 impl<'a> Peeps<'a> {
     pub fn new(p: &'a Parameters) -> Peeps<'a> {
+        if let Some(seed) = p.seed {
+            random::seed(seed);
+        }
+        return Peeps::new_island(p, p.challenge, p.island_map(0));
+    }
+
+    /// A population facing its own `challenge`, in a world laid out by its own `map`. Unlike
+    /// [`Peeps::new`], it doesn't restart the simulation's generator from the seed, so that the
+    /// islands of a simulation all differ.
+    pub fn new_island(p: &'a Parameters, challenge: Challenges, map: Option<&WorldMap>) -> Peeps<'a> {
         let mut population: Vec<Individual> = Vec::with_capacity(p.population as usize);
-        population.push(Individual::new(0, Coord(-1, -1), make_random_genome(1), &p));
+        population.push(Individual::new(0, 0, Coord(-1, -1), make_random_genome(1), p));
 
         let signals = Signals::new(1, p.size_x, p.size_y);
-        let move_queue = HashMap::new();
-        let death_queue = Vec::new();
-        let mut world = World::new(p.size_x, p.size_y);
-        let mut rng = rand::thread_rng();
+        let move_queue = MoveQueue::new(p.population);
+        let death_queue = DeathQueue::new(p.population);
+        let birth_queue = BirthQueue::new(p.population);
+        let eat_queue = EatQueue::new(p.population);
+        let kill_queue = KillQueue::new(p.population);
+        let mut world = match map {
+            Some(map) => World::with_map(map, p.topology),
+            None => World::new(p.size_x, p.size_y, p.topology),
+        };
+        world.food = Food::new(p, map);
+        let mut replay = ReplayLog::new(p.save_replay);
+        let mut lineage = Lineage::new();
+        let mut rng = random::rng();
 
+        // Id 0 is left for the placeholder individual at index 0
         for i in 1..=p.population {
             let empty_coord = world.find_random_empty_location();
             let genome_size = rng.gen_range(1..=p.max_genome_length);
-            let individual = Individual::new(i, empty_coord, make_random_genome(genome_size), &p);
-            world.set_at_coord(empty_coord, individual.index);
+            let id = i as IndividualId;
+            if p.track_lineage {
+                lineage.record_birth(id, 0, &[]);
+            }
+            let mut genome = make_founder_genome(genome_size, p);
+            genome.species = species_at_index(p, i);
+            let individual = Individual::new(i, id, empty_coord, genome, p);
+            world.set_at_coord(empty_coord, Cell::occupant(individual.index));
+            replay.record(ReplayEvent::Birth { index: i, location: empty_coord });
             population.insert(i as usize, individual);
         }
         return Peeps {
@@ -51,114 +121,632 @@ impl<'a> Peeps<'a> {
             signals,
             population,
             move_queue,
+            moves: Vec::new(),
             death_queue,
+            birth_queue,
+            eat_queue,
+            kill_queue,
+            free_indices: Vec::new(),
+            kills: 0,
+            births: 0,
+            deaths_of_age: 0,
+            starvations: 0,
+            period: 0,
+            mutation_counts: MutationCounts::default(),
+            replay,
+            lineage,
+            next_id: p.population as IndividualId + 1,
+            challenge,
             parameters: p
         };
     }
 
-    pub fn queue_for_death(death_queue: &mut DeathQueue, id: u16) {
+    pub fn queue_for_death(death_queue: &mut DeathQueue, id: IndividualIndex) {
         death_queue.push(id);
     }
 
+    // Dead individuals leave the world, so their cells are free for the others.
     pub fn drain_death_queue(&mut self) {
-        for id in self.death_queue.drain(..) {
-            let individual: &mut Individual = self.population.get_mut(id as usize).unwrap();
-            individual.alive = false;
+        let population = &mut self.population;
+        let (world, replay, kills, free_indices) = (&mut self.world, &mut self.replay, &mut self.kills, &mut self.free_indices);
+        self.death_queue.drain(|index| {
+            if Peeps::remove(&mut population[index as usize], world, replay, free_indices) {
+                *kills += 1;
+            }
+        });
+    }
+
+    // Removes a living individual from the world, and returns whether it was alive.
+    fn remove(individual: &mut Individual, world: &mut World, replay: &mut ReplayLog, free_indices: &mut Vec<IndividualIndex>) -> bool {
+        if !individual.alive {
+            return false;
         }
+        individual.alive = false;
+        world.set_at_coord(individual.location, Cell::EMPTY);
+        free_indices.push(individual.index);
+        replay.record(ReplayEvent::Death { index: individual.index });
+        return true;
     }
 
-    pub fn queue_for_move(move_queue: &mut MoveQueue, peep_index: u16, move_data: (f32, f32)) {
-        move_queue.entry(peep_index).or_insert(Vec::new()).push(move_data);
+    // In the continuous mode, every individual spends energy to live, and gains some while it meets
+    // the survival challenge. Those that got too old or ran out of energy die.
+    pub fn apply_metabolism(&mut self) {
+        let challenges = self.challenge_functions();
+        let p = self.parameters;
+        for individual in self.population.iter_mut().skip(1).filter(|i| i.alive) {
+            individual.energy -= p.energy_per_step;
+            let challenge = challenges[individual.genome.species as usize];
+            if challenge(individual, &self.world, &self.signals, p, vec![50]) {
+                individual.energy += p.challenge_energy_gain;
+            }
+
+            if individual.age >= p.max_age {
+                Peeps::remove(individual, &mut self.world, &mut self.replay, &mut self.free_indices);
+                self.deaths_of_age += 1;
+            } else if individual.energy <= 0.0 {
+                Peeps::remove(individual, &mut self.world, &mut self.replay, &mut self.free_indices);
+                self.starvations += 1;
+            }
+        }
     }
 
-    pub fn drain_move_queue(&mut self) {
-        for (id, urges) in self.move_queue.drain() {
-            let individual: &mut Individual = self.population.get_mut(id as usize).unwrap();
-            // sum the urges
-            let mut sum_urges = (0.0, 0.0);
-            for urge in urges {
-                sum_urges.0 += urge.0;
-                sum_urges.1 += urge.1;
+    // Challenge of each species, which is the population's one unless the species has its own. There's
+    // a single one when there are no species.
+    fn challenge_functions(&self) -> Vec<ChallengeFunction> {
+        if self.parameters.species.is_empty() {
+            return vec![get_challenge_function(self.challenge)];
+        }
+        return self.parameters.species.iter()
+            .map(|species| get_challenge_function(species.challenge.unwrap_or(self.challenge)))
+            .collect();
+    }
+
+    // Each queued parent with enough energy has a child in an empty cell near it, as long as the
+    // population has room for it. The child starts with the energy its parent spends on it. With
+    // sexual reproduction, the other parent is a random living individual of its species, or a random
+    // neighbour of its species within the `mating_radius` when it's set.
+    pub fn drain_birth_queue(&mut self) {
+        let mut parents = Vec::new();
+        self.birth_queue.drain(|index| parents.push(index));
+        if parents.is_empty() {
+            return;
+        }
+
+        let p = self.parameters;
+        let living: Vec<IndividualIndex> = self.population.iter().skip(1).filter(|i| i.alive).map(|i| i.index).collect();
+        for parent_index in parents {
+            let parent = &self.population[parent_index as usize];
+            if !parent.alive || parent.energy < p.reproduction_energy {
+                continue;
+            }
+            let Some(&child_index) = self.free_indices.last() else {
+                break;
+            };
+            let Some(location) = self.world.find_empty_location_near(parent.location, p.birth_radius as i16) else {
+                continue;
+            };
+
+            let mate_index = match (p.sexual_reproduction, p.mating_radius) {
+                (false, _) => parent_index,
+                (true, None) => {
+                    let mates: Vec<IndividualIndex> = living.iter().copied()
+                        .filter(|&i| self.population[i as usize].genome.species == parent.genome.species)
+                        .collect();
+                    *mates.choose(&mut random::rng()).unwrap_or(&parent_index)
+                }
+                (true, Some(radius)) => {
+                    let mut neighbours = Vec::new();
+                    let neighborhood = Neighborhood::new(p.neighborhood_shape, radius as i16);
+                    self.world.apply_to_occupants_in_neighborhood(parent.location, neighborhood, |c| {
+                        if c != parent.location {
+                            neighbours.extend(self.world.at_coord(c).occupant_index()
+                                .filter(|&i| self.population[i as usize].genome.species == parent.genome.species));
+                        }
+                    });
+                    *neighbours.choose(&mut random::rng()).unwrap_or(&parent_index)
+                }
+            };
+            let mate = &self.population[mate_index as usize];
+            let genome = breed_from_parents(&parent.genome, &mate.genome, p, &mut self.mutation_counts);
+            let parent_ids = [parent.id, mate.id];
+
+            let id = self.next_id;
+            self.next_id += 1;
+            if p.track_lineage {
+                let parents = if mate_index == parent_index { &parent_ids[..1] } else { &parent_ids[..] };
+                self.lineage.record_birth(id, self.period, parents);
             }
 
-            // Normalize the urges
-            sum_urges.0 = f32::tanh(sum_urges.0);
-            sum_urges.1 = f32::tanh(sum_urges.1);
+            self.free_indices.pop();
+            self.population[parent_index as usize].energy -= p.reproduction_energy;
+            let mut child = Individual::new(child_index, id, location, genome, p);
+            child.energy = p.reproduction_energy;
+            self.world.set_at_coord(location, Cell::occupant(child_index));
+            self.replay.record(ReplayEvent::Birth { index: child_index, location });
+            self.population[child_index as usize] = child;
+            self.births += 1;
+        }
+    }
 
-            //adjust to response
-            let response = Individual::response_curve(individual.responsiveness,
-                                                      self.parameters.responsiveness_curve_k_factor as f32);
-            sum_urges.0 *= response;
-            sum_urges.1 *= response;
+    // Each queued individual eats from its cell and gains as much energy as the food it took. The
+    // food is shared in index order, so the last ones may find the cell empty.
+    pub fn drain_eat_queue(&mut self) {
+        let population = &mut self.population;
+        let (food, food_per_bite) = (&mut self.world.food, self.parameters.food_per_bite);
+        self.eat_queue.drain(|index| {
+            let individual: &mut Individual = &mut population[index as usize];
+            if individual.alive {
+                individual.energy += food.take(individual.location, food_per_bite);
+            }
+        });
+    }
 
-            //Convert to direction
-            let move_x = probability_to_bool(sum_urges.0);
-            let move_y = probability_to_bool(sum_urges.1);
-            let sign_x = if sum_urges.0 > 0.0 { 1 } else { -1 };
-            let sign_y = if sum_urges.1 > 0.0 { 1 } else { -1 };
-            let coord = individual.location  + Coord(sign_x * move_x as i16, sign_y * move_y as i16);
-            if self.world.is_in_bounds( coord) && self.world.is_empty_at(coord) {
-                self.world.set_at_coord(coord, id);
-                self.world.set_at_coord(individual.location, EMPTY_CELL);
-                individual.location = coord;
+    // Each living killer removes the individual in front of it right away, before anybody moves, so
+    // the victims neither move nor kill during the step. Killers go in index order.
+    pub fn drain_kill_queue(&mut self) {
+        let mut killers = Vec::new();
+        self.kill_queue.drain(|index| killers.push(index));
+        for index in killers {
+            let killer = &self.population[index as usize];
+            if !killer.alive {
+                continue;
+            }
+            let victim = self.world.resolve(killer.location + killer.last_move_direction)
+                .and_then(|location| self.world.at_coord(location).occupant_index());
+            if let Some(victim) = victim.filter(|&victim| victim != index) {
+                if Peeps::remove(&mut self.population[victim as usize], &mut self.world, &mut self.replay, &mut self.free_indices) {
+                    self.kills += 1;
+                }
             }
         }
     }
 
-    pub fn individual_at(population: &'a Vec<Individual>, world: &World, coord: Coord) -> Option<&'a Individual> {
-        return population.get(world.at_coord(coord) as usize)
+    pub fn queue_for_move(move_queue: &mut MoveQueue, peep_index: IndividualIndex, move_data: (f32, f32)) {
+        move_queue.push(peep_index, move_data);
+    }
+
+    // Moves are applied one at a time, in the order set by the `collision_policy` parameter. An
+    // individual only moves if the cell it wants to enter is still empty when its turn comes.
+    pub fn drain_move_queue(&mut self) {
+        let mut moves = std::mem::take(&mut self.moves);
+        moves.clear();
+        let population = &self.population;
+        let k_factor = self.parameters.responsiveness_curve_k_factor as f32;
+        self.move_queue.drain(|index, urge| {
+            let individual = &population[index as usize];
+            if !individual.alive {
+                return;
+            }
+            // Normalize the urges and adjust them to the individual's responsiveness
+            let response = Individual::response_curve(individual.responsiveness, k_factor);
+            moves.push((index, (f32::tanh(urge.0) * response, f32::tanh(urge.1) * response)));
+        });
+        order_moves(&mut moves, self.parameters.collision_policy, &mut random::rng());
+
+        for (index, urge) in moves.iter() {
+            // Each axis moves with a probability given by the strength of its urge
+            let step_x = probability_to_bool(urge.0.abs()) as i16 * if urge.0 > 0.0 { 1 } else { -1 };
+            let step_y = probability_to_bool(urge.1.abs()) as i16 * if urge.1 > 0.0 { 1 } else { -1 };
+            let step = Coord(step_x, step_y);
+            if step == Coord(0, 0) {
+                continue;
+            }
+
+            let individual: &mut Individual = &mut self.population[*index as usize];
+            let target = self.world.resolve(individual.location + step);
+            if let Some(target) = target.filter(|c| self.world.is_empty_at(*c)) {
+                individual.energy -= self.parameters.move_energy_cost;
+                self.world.set_at_coord(target, Cell::occupant(*index));
+                self.world.set_at_coord(individual.location, Cell::EMPTY);
+                individual.location = target;
+                individual.last_move_direction = Dir::from(step);
+                self.replay.record(ReplayEvent::Move { index: *index, location: target });
+            }
+        }
+        self.moves = moves;
+    }
+
+    pub fn individual_at(population: &'a [Individual], world: &World, coord: Coord) -> Option<&'a Individual> {
+        return world.at_coord(coord).occupant_index().and_then(|index| population.get(index as usize));
     }
 
     pub fn simulate_all(&mut self, parameters: &Parameters, simulation_step: u32) {
         //Collect all the genomes
         let mut genomes_copy: Vec<Genome> = self.population.iter().skip(1).map(|i| i.genome.clone()).collect::<Vec<_>>();
-        for individual in self.population.iter_mut().skip(1) {
-            individual.simulate(&mut genomes_copy, &mut self.world, &mut self.signals, &parameters,
-                                &mut self.death_queue, &mut self.move_queue, simulation_step);
+        for individual in self.population.iter_mut().skip(1).filter(|i| i.alive) {
+            individual.simulate(&mut genomes_copy, &mut self.world, &mut self.signals, parameters,
+                                &mut self.death_queue, &mut self.birth_queue, &mut self.eat_queue, &mut self.kill_queue, &mut self.move_queue, simulation_step);
         }
 
+        self.drain_eat_queue();
+        self.drain_kill_queue();
         self.drain_move_queue();
         self.drain_death_queue();
+        if parameters.generation_mode == GenerationMode::Continuous {
+            self.apply_metabolism();
+            self.drain_birth_queue();
+        }
+        self.world.food.grow();
+        self.replay.record(ReplayEvent::StepEnd { step: simulation_step });
     }
 
-    pub fn end_generation(&mut self) {
-        let challenge = get_challenge_function(Challenges::Circle);
-        // Get all the genomes from individuals that survived the challenge
-        let genomes: Vec<Genome> = self.population.iter().skip(1)
+    // Replaces the population with the offspring of the survivors and returns how many survived.
+    pub fn end_generation(&mut self, generation: u32) -> u32 {
+        let challenges = self.challenge_functions();
+        // Get all the individuals that survived the challenge of their species
+        let survivors: Vec<&Individual> = self.population.iter().skip(1)
             .filter(|&i| {
-                challenge(i, &self.world, &self.signals, &self.parameters, vec![50])
+                challenges[i.genome.species as usize](i, &self.world, &self.signals, self.parameters, vec![50])
             })
-            .map(|i| i.genome.clone())
             .collect();
+        let parents: Vec<Parent> = survivors.iter().map(|&i| Parent::from(i)).collect();
+        let survivor_indices: Vec<u32> = survivors.iter().map(|i| i.index).collect();
 
-        self.new_generation(&genomes);
+        self.replay.record(ReplayEvent::GenerationEnd { generation, survivors: survivor_indices });
+        self.new_generation(&parents, generation + 1);
+        if self.parameters.track_lineage {
+            let living: Vec<IndividualId> = self.living_ids();
+            self.lineage.prune(&living);
+        }
+        return parents.len() as u32;
     }
 
-    pub fn new_generation(&mut self, genomes: &Vec<Genome>) {
+    // Ends a period of the continuous mode, and returns how many individuals are alive. The
+    // population is only replaced if it died out, by founders as in a generation without parents.
+    pub fn end_period(&mut self, period: u32) -> u32 {
+        let living: Vec<IndividualIndex> = self.population.iter().skip(1).filter(|i| i.alive).map(|i| i.index).collect();
+        self.replay.record(ReplayEvent::GenerationEnd { generation: period, survivors: living.clone() });
+        if living.is_empty() {
+            self.new_generation(&[], period + 1);
+        } else {
+            self.period = period + 1;
+            self.kills = 0;
+            self.births = 0;
+            self.deaths_of_age = 0;
+            self.starvations = 0;
+            self.mutation_counts = MutationCounts::default();
+        }
+        if self.parameters.track_lineage {
+            let living: Vec<IndividualId> = self.living_ids();
+            self.lineage.prune(&living);
+        }
+        return living.len() as u32;
+    }
+
+    // Populates the world with the offspring of `parents`. Each species has as many children as in
+    // the first generation, from its own parents or from founders if none survived. With a
+    // `mating_radius`, the second parent of each child is a neighbour of the first one, and the
+    // child is born near the first one.
+    pub fn new_generation(&mut self, parents: &[Parent], generation: u32) {
         self.world.zero_fill();
+        self.world.food.refill();
         self.population.clear();
-        self.population.push(Individual::new(0, Coord(-1, -1), make_random_genome(1), self.parameters));
+        self.free_indices.clear();
+        self.period = generation;
+        self.kills = 0;
+        self.births = 0;
+        self.deaths_of_age = 0;
+        self.starvations = 0;
+        self.mutation_counts = MutationCounts::default();
+        self.population.push(Individual::new(0, 0, Coord(-1, -1), make_random_genome(1), self.parameters));
+
+
+        let p = self.parameters;
+        // Position of each parent in `parents`, plus one, at its location
+        let local_mating = p.mating_radius.map(|radius| {
+            let mut positions = Grid::<u32>::with_topology(p.size_x, p.size_y, p.topology);
+            for (position, parent) in parents.iter().enumerate() {
+                positions.set_at_coord(parent.location, position as u32 + 1);
+            }
+            (positions, Neighborhood::new(p.neighborhood_shape, radius as i16))
+        });
 
+        // Positions in `parents` of the parents of each species
+        let mut pools: Vec<Vec<usize>> = vec![Vec::new(); p.species.len().max(1)];
+        for (position, parent) in parents.iter().enumerate() {
+            pools[parent.genome.species as usize].push(position);
+        }
 
-        let mut rng = rand::thread_rng();
+        let mut rng = random::rng();
         for i in 1..=self.parameters.population {
-            let random_father = genomes.choose(&mut rng);
-            let random_mother = genomes.choose(&mut rng);
-            let child_location = self.world.find_random_empty_location();
+            let species = species_at_index(p, i);
+            let pool = &pools[species as usize];
+            let random_father = pool.choose(&mut rng).map(|&position| &parents[position]);
+            let random_mother = match (&local_mating, random_father) {
+                (Some((positions, neighborhood)), Some(father)) =>
+                    Some(Peeps::choose_local_mate(parents, positions, father, *neighborhood)),
+                _ => pool.choose(&mut rng).map(|&position| &parents[position]),
+            };
+            let child_location = match (&local_mating, random_father) {
+                (Some(_), Some(father)) => self.world.find_empty_location_near(father.location, p.birth_radius as i16),
+                _ => None,
+            }.unwrap_or_else(|| self.world.find_random_empty_location());
+            let id = self.next_id;
+            self.next_id += 1;
 
             // If any of the parents is None, child is random
             let child = if let (Some(father), Some(mother)) = (random_father, random_mother) {
-                breed_from_parents(father, mother, &self.parameters)
+                if self.parameters.track_lineage {
+                    self.lineage.record_birth(id, generation, &[mother.id, father.id]);
+                }
+                breed_from_parents(&father.genome, &mother.genome, self.parameters, &mut self.mutation_counts)
             } else {
+                if self.parameters.track_lineage {
+                    self.lineage.record_birth(id, generation, &[]);
+                }
                 let genome_size = rng.gen_range(1..=self.parameters.max_genome_length);
-                make_random_genome(genome_size)
+                let mut genome = make_founder_genome(genome_size, self.parameters);
+                genome.species = species;
+                genome
             };
 
-            self.population.insert(i as usize, Individual::new(i, child_location, child, &self.parameters));
+            self.world.set_at_coord(child_location, Cell::occupant(i));
+            self.replay.record(ReplayEvent::Birth { index: i, location: child_location });
+            self.population.insert(i as usize, Individual::new(i, id, child_location, child, self.parameters));
+        }
+    }
+
+    // A random parent of the same species in the neighborhood of `parent`, or the parent itself if it
+    // has no such neighbours.
+    fn choose_local_mate<'p>(parents: &'p [Parent], positions: &Grid<u32>, parent: &'p Parent,
+                             neighborhood: Neighborhood) -> &'p Parent {
+        let mut neighbours = Vec::new();
+        positions.apply_neighborhood_to_f(parent.location, neighborhood, |c| {
+            let position = positions.at_coord(c);
+            if position > 0 && c != parent.location && parents[position as usize - 1].genome.species == parent.genome.species {
+                neighbours.push(position - 1);
+            }
+        });
+        return neighbours.choose(&mut random::rng()).map_or(parent, |&position| &parents[position as usize]);
+    }
+
+    // Copies of the genomes of `count` random living individuals, to send to another island.
+    pub fn choose_emigrants(&self, count: u32) -> Vec<Genome> {
+        let living: Vec<&Individual> = self.population.iter().skip(1).filter(|i| i.alive).collect();
+        return living.choose_multiple(&mut random::rng(), count as usize).map(|i| i.genome.clone()).collect();
+    }
+
+    // Migrants from another island take the places of distinct random living individuals of their
+    // species, as long as there are enough of them. Their ancestors aren't known here, so the lineage
+    // records them as founders. Returns how many settled.
+    pub fn immigrate(&mut self, genomes: Vec<Genome>, generation: u32) -> u32 {
+        let mut living: Vec<IndividualIndex> = self.population.iter().skip(1).filter(|i| i.alive).map(|i| i.index).collect();
+        living.shuffle(&mut random::rng());
+        let mut settled = 0;
+        for genome in genomes {
+            let species = genome.species;
+            let Some(place) = living.iter().position(|&i| self.population[i as usize].genome.species == species) else {
+                continue;
+            };
+            let index = living.swap_remove(place);
+            let id = self.next_id;
+            self.next_id += 1;
+            if self.parameters.track_lineage {
+                self.lineage.record_birth(id, generation, &[]);
+            }
+            let location = self.population[index as usize].location;
+            self.replay.record(ReplayEvent::Death { index });
+            self.replay.record(ReplayEvent::Birth { index, location });
+            self.population[index as usize] = Individual::new(index, id, location, genome, self.parameters);
+            settled += 1;
         }
+        return settled;
+    }
+
+    pub fn living_ids(&self) -> Vec<IndividualId> {
+        return self.population.iter().skip(1).filter(|i| i.alive).map(|i| i.id).collect();
     }
 
     pub fn get_population_locations(&self) -> Vec<(f64,f64)> {
         self.population.iter().map(|i| (i.location.0 as f64, i.location.1 as f64)).collect()
     }
-}
\ No newline at end of file
+}
+//<editor-fold desc="Unit tests">
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::simulation::food::FoodPattern;
+
+    fn continuous_parameters() -> Parameters {
+        let mut p = Parameters::defaults();
+        p.size_x = 32;
+        p.size_y = 32;
+        p.population = 50;
+        p.generation_mode = GenerationMode::Continuous;
+        p.seed = Some(3);
+        return p;
+    }
+
+    #[test]
+    fn test_natural_deaths() {
+        let mut p = continuous_parameters();
+        p.max_age = 4;
+        p.initial_energy = 1000.0;
+        let mut peeps = Peeps::new(&p);
+        for step in 0..4 {
+            peeps.simulate_all(&p, step);
+        }
+        assert_eq!(peeps.living_ids().len(), 0);
+        assert_eq!(peeps.deaths_of_age + peeps.starvations, 50);
+        assert_eq!(peeps.starvations, 0);
+        assert!(peeps.world.find_empty_location_near(Coord(16, 16), 16).is_some());
+
+        // A population that died out is replaced by founders
+        assert_eq!(peeps.end_period(0), 0);
+        assert_eq!(peeps.living_ids().len(), 50);
+        assert_eq!(peeps.deaths_of_age, 0);
+
+        p.max_age = 1000;
+        p.initial_energy = 2.5;
+        p.challenge_energy_gain = 0.0;
+        let mut peeps = Peeps::new(&p);
+        for step in 0..3 {
+            peeps.simulate_all(&p, step);
+        }
+        assert_eq!(peeps.starvations, 50);
+    }
+
+    #[test]
+    fn test_births_take_free_slots() {
+        let p = continuous_parameters();
+        let mut peeps = Peeps::new(&p);
+        // Without a free slot, nobody is born
+        peeps.birth_queue.push(1);
+        peeps.drain_birth_queue();
+        assert_eq!(peeps.births, 0);
+
+        peeps.death_queue.push(7);
+        peeps.drain_death_queue();
+        peeps.population[1].energy = p.reproduction_energy - 1.0;
+        peeps.population[2].energy = p.reproduction_energy + 1.0;
+        peeps.birth_queue.push(1);
+        peeps.birth_queue.push(2);
+        peeps.drain_birth_queue();
+        assert_eq!(peeps.births, 1);
+        assert_eq!(peeps.population[2].energy, 1.0);
+
+        let child = &peeps.population[7];
+        assert!(child.alive);
+        assert_eq!(child.energy, p.reproduction_energy);
+        assert_eq!(child.id, p.population as IndividualId + 1);
+        assert_eq!(peeps.world.at_coord(child.location), Cell::occupant(7));
+        let offset = peeps.world.displacement(peeps.population[2].location, child.location);
+        assert!(offset.0.abs() <= p.birth_radius as i16 && offset.1.abs() <= p.birth_radius as i16);
+    }
+
+    #[test]
+    fn test_large_world_generation() {
+        let mut p = Parameters::defaults();
+        p.size_x = 1000;
+        p.size_y = 1000;
+        p.population = 2000;
+        p.challenge = Challenges::Circle;
+        let mut peeps = Peeps::new(&p);
+        peeps.population[1].location = Coord(0, 0);
+        let expected = peeps.population.iter().skip(1)
+            .filter(|i| (500 - i.location.0 as i32).pow(2) + (500 - i.location.1 as i32).pow(2) < 50)
+            .count();
+        assert_eq!(peeps.end_generation(0), expected as u32);
+        assert_eq!(peeps.population.len(), 2001);
+    }
+
+    #[test]
+    fn test_local_mating() {
+        let mut p = Parameters::defaults();
+        p.size_x = 64;
+        p.size_y = 64;
+        p.population = 40;
+        p.mating_radius = Some(3);
+        p.birth_radius = 4;
+        p.track_lineage = true;
+        p.seed = Some(5);
+        let mut peeps = Peeps::new(&p);
+        // Two groups of parents, too far from each other to mate
+        let parent = |id: IndividualId, location: Coord| Parent { id, genome: make_founder_genome(4, &p), location };
+        let parents = vec![parent(1, Coord(5, 5)), parent(2, Coord(6, 7)), parent(3, Coord(50, 50)), parent(4, Coord(52, 49))];
+        peeps.new_generation(&parents, 1);
+
+        for child in peeps.population.iter().skip(1) {
+            let record = peeps.lineage.get(child.id).unwrap();
+            assert_eq!(record.parents.len(), 2);
+            let (first, second) = (record.parents[0], record.parents[1]);
+            assert_ne!(first, second);
+            assert_eq!(first <= 2, second <= 2);
+
+            let group = if first <= 2 { Coord(5, 5) } else { Coord(50, 50) };
+            let offset = peeps.world.displacement(group, child.location);
+            assert!(offset.0.abs() <= 8 && offset.1.abs() <= 8, "{:?} is far from its parents", child.location);
+        }
+    }
+
+    #[test]
+    fn test_species_breed_apart() {
+        let mut p = Parameters::defaults();
+        p.size_x = 32;
+        p.size_y = 32;
+        p.population = 30;
+        p.track_lineage = true;
+        p.species = serde_yaml::from_str("
+            - { name: prey, population: 20 }
+            - { name: predator, population: 10, challenge: energy }
+        ").unwrap();
+        let mut peeps = Peeps::new(&p);
+        assert_eq!(peeps.population.iter().skip(1).filter(|i| i.genome.species == 1).count(), 10);
+
+        // The predators all pass their own challenge, and the prey died out
+        for individual in peeps.population.iter_mut().skip(1).filter(|i| i.genome.species == 0) {
+            individual.alive = false;
+        }
+        assert_eq!(peeps.end_generation(0), 10);
+        for child in peeps.population.iter().skip(1) {
+            let expected_species = if child.index <= 20 { 0 } else { 1 };
+            assert_eq!(child.genome.species, expected_species);
+            let parents = &peeps.lineage.get(child.id).unwrap().parents;
+            if expected_species == 0 {
+                assert!(parents.is_empty());
+            } else {
+                assert_eq!(parents.len(), 2);
+                assert!(parents.iter().all(|&id| id > 20 && id <= 30));
+            }
+        }
+    }
+
+    #[test]
+    fn test_kill_forward() {
+        let mut p = Parameters::defaults();
+        p.size_x = 16;
+        p.size_y = 16;
+        p.population = 3;
+        let mut peeps = Peeps::new(&p);
+        for (index, location, direction) in [(1, Coord(4, 4), Coord(1, 0)), (2, Coord(5, 4), Coord(-1, 0)), (3, Coord(10, 10), Coord(1, 0))] {
+            let individual = &mut peeps.population[index];
+            peeps.world.set_at_coord(individual.location, Cell::EMPTY);
+            individual.location = location;
+            individual.last_move_direction = Dir::from(direction);
+        }
+        for index in 1..=3 {
+            peeps.world.set_at_coord(peeps.population[index].location, Cell::occupant(index as IndividualIndex));
+        }
+
+        // Individuals 1 and 2 face each other, and individual 3 faces nobody. Individual 1 kills
+        // first, so individual 2 neither kills nor moves.
+        peeps.move_queue.push(2, (0.0, 10.0));
+        for index in 1..=3 {
+            peeps.kill_queue.push(index);
+        }
+        peeps.drain_kill_queue();
+        peeps.drain_move_queue();
+        peeps.drain_death_queue();
+        assert_eq!(peeps.kills, 1);
+        assert!(!peeps.population[2].alive && peeps.population[1].alive && peeps.population[3].alive);
+        assert!(peeps.world.is_empty_at(Coord(5, 4)) && peeps.world.is_empty_at(Coord(5, 5)));
+        assert_eq!(peeps.population[2].location, Coord(5, 4));
+    }
+
+    #[test]
+    fn test_eating() {
+        let mut p = Parameters::defaults();
+        p.size_x = 32;
+        p.size_y = 32;
+        p.population = 10;
+        p.food_pattern = FoodPattern::Uniform;
+        p.food_max_per_cell = 3.0;
+        p.food_per_bite = 2.0;
+        p.food_growth_rate = 0.0;
+        let mut peeps = Peeps::new(&p);
+        let location = peeps.population[1].location;
+        let energy = peeps.population[1].energy;
+        for _ in 0..2 {
+            peeps.eat_queue.push(1);
+            peeps.drain_eat_queue();
+        }
+        // The second bite only finds what the first one left
+        assert_eq!(peeps.population[1].energy, energy + 3.0);
+        assert_eq!(peeps.world.food.get(location), 0.0);
+
+        // The food comes back with the next generation
+        peeps.new_generation(&[], 1);
+        assert_eq!(peeps.world.food.get(location), 3.0);
+    }
+}
+//</editor-fold>
@@ -1,34 +1,69 @@
+pub mod mask;
+pub mod region;
+
+use serde::{Serialize, Deserialize};
 use crate::Parameters;
 use crate::population::individual::Individual;
+use crate::simulation::probability_to_bool;
 use crate::simulation::signals::Signals;
 use crate::simulation::world::World;
 
+/// Criterion the individuals must meet to survive a generation.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
 pub enum Challenges {
+    // Being close to the center of the world
     Circle,
+    // Having at least `energy_threshold` energy left
+    Energy,
+    // Being inside `challenge_region`
+    Region,
+    // Surviving with the probability `challenge_mask` gives to the cell
+    Mask,
 }
 
-pub fn get_challenge_function(challenge: Challenges) -> fn(&Individual, &World, &Signals, &Parameters, Vec<i16>) -> bool {
+pub type ChallengeFunction = fn(&Individual, &World, &Signals, &Parameters, Vec<i16>) -> bool;
+
+pub fn get_challenge_function(challenge: Challenges) -> ChallengeFunction {
     match challenge {
         Challenges::Circle => circle_challenge,
+        Challenges::Energy => energy_challenge,
+        Challenges::Region => region_challenge,
+        Challenges::Mask => mask_challenge,
     }
 }
 
+pub fn region_challenge(individual: &Individual, world: &World, _signals: &Signals, parameters: &Parameters,
+                        _arguments: Vec<i16>) -> bool {
+    return individual.alive && parameters.challenge_region.as_ref().is_some_and(|r| r.contains(individual.location, world));
+}
+
+pub fn mask_challenge(individual: &Individual, _world: &World, _signals: &Signals, parameters: &Parameters,
+                      _arguments: Vec<i16>) -> bool {
+    let Some(mask) = parameters.survival_mask.as_ref().filter(|_| individual.alive) else {
+        return false;
+    };
+    return probability_to_bool(mask.probability(individual.location));
+}
+
+pub fn energy_challenge(individual: &Individual, _world: &World, _signals: &Signals, parameters: &Parameters,
+                        _arguments: Vec<i16>) -> bool {
+    return individual.alive && individual.energy >= parameters.energy_threshold;
+}
+
 pub fn circle_challenge(individual: &Individual, world: &World, _signals: &Signals, _parameters: &Parameters,
                         arguments: Vec<i16>) -> bool {
     if !individual.alive {
         return false;
     }
 
-    let radius = arguments[0];
+    let radius = arguments[0] as i32;
 
-    let center_x = (world.width / 2) as i16;
-    let center_y = (world.height / 2) as i16;
+    let center_x = (world.width / 2) as i32;
+    let center_y = (world.height / 2) as i32;
 
-    // The individuals within a distance of the world's center survive.
-    let distance_from_center = (center_x - individual.location.0).pow(2) + (center_y - individual.location.1).pow(2);
-    if distance_from_center < radius {
-        return true;
-    } else {
-        return false;
-    }
+    // The individuals within a distance of the world's center survive. The squares overflow an
+    // i16 on worlds wider than 362 cells.
+    let distance_from_center = (center_x - individual.location.0 as i32).pow(2) + (center_y - individual.location.1 as i32).pow(2);
+    return distance_from_center < radius;
 }
\ No newline at end of file
@@ -0,0 +1,88 @@
+use std::error::Error;
+use std::path::Path;
+use crate::render::frame::Frame;
+use crate::simulation::types::Coord;
+
+/// Survival probability of every cell of the world, read from a grayscale image: individuals on
+/// a white pixel always survive, on a black one they never do. The image is stretched over the
+/// world, with north at the top, like the frames of [`Frame`](crate::render::frame::Frame).
+#[derive(Debug, Clone, PartialEq)]
+pub struct SurvivalMask {
+    width: u16,
+    height: u16,
+    probabilities: Vec<f32>,
+}
+
+impl SurvivalMask {
+    /// Samples an image given as rows of 8-bit gray levels, from the top row down.
+    pub fn from_gray_levels(image_width: u32, image_height: u32, levels: &[u8], width: u16, height: u16) -> SurvivalMask {
+        let mut probabilities = Vec::with_capacity(width as usize * height as usize);
+        for y in 0..height as u32 {
+            // The bottom row of the world is the last one of the image
+            let row = (height as u32 - 1 - y) * image_height / height as u32;
+            for x in 0..width as u32 {
+                let column = x * image_width / width as u32;
+                probabilities.push(levels[(row * image_width + column) as usize] as f32 / 255.0);
+            }
+        }
+        return SurvivalMask { width, height, probabilities };
+    }
+
+    /// Reads a PNG image of any color type. Colors count by their luminance.
+    pub fn read_png(path: &str, width: u16, height: u16) -> Result<SurvivalMask, Box<dyn Error>> {
+        let image = Frame::read_png(Path::new(path))?;
+        let levels: Vec<u8> = image.pixels.chunks(3)
+            .map(|pixel| (0.299 * pixel[0] as f32 + 0.587 * pixel[1] as f32 + 0.114 * pixel[2] as f32).round() as u8)
+            .collect();
+        return Ok(SurvivalMask::from_gray_levels(image.width, image.height, &levels, width, height));
+    }
+
+    /// Everybody survives inside the zone, and nobody outside.
+    pub fn from_zone(width: u16, height: u16, zone: &[Coord]) -> SurvivalMask {
+        let mut probabilities = vec![0.0; width as usize * height as usize];
+        for location in zone {
+            probabilities[location.1 as usize * width as usize + location.0 as usize] = 1.0;
+        }
+        return SurvivalMask { width, height, probabilities };
+    }
+
+    /// Outside the world, nobody survives.
+    pub fn probability(&self, location: Coord) -> f32 {
+        if location.0 < 0 || location.1 < 0 || location.0 as u16 >= self.width || location.1 as u16 >= self.height {
+            return 0.0;
+        }
+        return self.probabilities[location.1 as usize * self.width as usize + location.0 as usize];
+    }
+}
+
+//<editor-fold desc="Unit tests">
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::render::frame::Frame;
+
+    #[test]
+    fn test_mask_is_stretched_with_north_up() {
+        // A white top row over a black one, spread over a 4x4 world
+        let mask = SurvivalMask::from_gray_levels(2, 2, &[255, 255, 0, 51], 4, 4);
+        assert_eq!(mask.probability(Coord(0, 3)), 1.0);
+        assert_eq!(mask.probability(Coord(3, 2)), 1.0);
+        assert_eq!(mask.probability(Coord(1, 1)), 0.0);
+        assert_eq!(mask.probability(Coord(3, 0)), 0.2);
+        assert_eq!(mask.probability(Coord(4, 0)), 0.0);
+    }
+
+    #[test]
+    fn test_read_png() {
+        let mut frame = Frame::new(2, 1, 1);
+        frame.fill_cell(Coord(0, 0), [0, 0, 0]);
+        let path = std::env::temp_dir().join(format!("biosim-mask-{}.png", std::process::id()));
+        frame.write_png(&path).unwrap();
+        let mask = SurvivalMask::read_png(path.to_str().unwrap(), 2, 1).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(mask.probability(Coord(0, 0)), 0.0);
+        assert_eq!(mask.probability(Coord(1, 0)), 1.0);
+        assert!(SurvivalMask::read_png("missing.png", 2, 1).is_err());
+    }
+}
+//</editor-fold>
@@ -0,0 +1,120 @@
+use serde::{Serialize, Deserialize};
+use crate::simulation::grid::Topology;
+use crate::simulation::types::Coord;
+use crate::simulation::world::World;
+
+/// Part of the world, described in the parameters and combined from simple shapes, e.g.
+///
+/// ```yaml
+/// challenge: region
+/// challenge_region:
+///   or:
+///     - rectangle: { min: [0, 0], max: [9, 127] }
+///     - and:
+///         - circle: { center: [64, 64], radius: 20 }
+///         - not:
+///             half_plane: { point: [64, 64], normal: [0, 1] }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Region {
+    // Cells between the two corners, both included
+    Rectangle { min: [i16; 2], max: [i16; 2] },
+    // Cells at most `radius` away from the center. The distance wraps around in a torus.
+    Circle { center: [i16; 2], radius: f32 },
+    // Cells on the side of the line through `point` that `normal` points to, including the line
+    HalfPlane { point: [i16; 2], normal: [f32; 2] },
+    And(Vec<Region>),
+    Or(Vec<Region>),
+    Not(Box<Region>),
+}
+
+impl Region {
+    pub fn contains(&self, location: Coord, world: &World) -> bool {
+        match self {
+            Region::Rectangle { min, max } => {
+                return (min[0]..=max[0]).contains(&location.0) && (min[1]..=max[1]).contains(&location.1);
+            }
+            Region::Circle { center, radius } => {
+                let dx = offset(center[0], location.0, world.width, world.topology);
+                let dy = offset(center[1], location.1, world.height, world.topology);
+                return dx * dx + dy * dy <= radius * radius;
+            }
+            Region::HalfPlane { point, normal } => {
+                let dx = offset(point[0], location.0, world.width, Topology::Bounded);
+                let dy = offset(point[1], location.1, world.height, Topology::Bounded);
+                return dx * normal[0] + dy * normal[1] >= 0.0;
+            }
+            Region::And(regions) => regions.iter().all(|r| r.contains(location, world)),
+            Region::Or(regions) => regions.iter().any(|r| r.contains(location, world)),
+            Region::Not(region) => !region.contains(location, world),
+        }
+    }
+}
+
+// Like World::displacement along one axis, but computed in i32: the points of the regions can be
+// anywhere in the i16 range, far outside the world.
+fn offset(from: i16, to: i16, length: u16, topology: Topology) -> f32 {
+    let offset = to as i32 - from as i32;
+    if topology == Topology::Bounded {
+        return offset as f32;
+    }
+    let length = length as i32;
+    let offset = offset.rem_euclid(length);
+    if offset > length / 2 {
+        return (offset - length) as f32;
+    }
+    return offset as f32;
+}
+
+//<editor-fold desc="Unit tests">
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_composed_region() {
+        let region: Region = serde_yaml::from_str("
+            or:
+              - rectangle: { min: [0, 0], max: [1, 9] }
+              - and:
+                  - circle: { center: [5, 5], radius: 2 }
+                  - not:
+                      half_plane: { point: [5, 5], normal: [1, 0] }
+        ").unwrap();
+        let world = World::new(10, 10, Topology::Bounded);
+        assert!(region.contains(Coord(0, 9), &world));
+        assert!(region.contains(Coord(1, 0), &world));
+        assert!(!region.contains(Coord(2, 0), &world));
+        // The west half of the circle, without the line through its center
+        assert!(region.contains(Coord(4, 5), &world));
+        assert!(region.contains(Coord(3, 5), &world));
+        assert!(!region.contains(Coord(5, 5), &world));
+        assert!(!region.contains(Coord(6, 5), &world));
+        assert!(!region.contains(Coord(3, 3), &world));
+    }
+
+    #[test]
+    fn test_circle_wraps_around_a_torus() {
+        let region = Region::Circle { center: [0, 0], radius: 1.5 };
+        assert!(region.contains(Coord(9, 9), &World::new(10, 10, Topology::Torus)));
+        assert!(!region.contains(Coord(9, 9), &World::new(10, 10, Topology::Bounded)));
+    }
+
+    #[test]
+    fn test_points_far_outside_the_world() {
+        let world = World::new(10, 10, Topology::Bounded);
+        let torus = World::new(10, 10, Topology::Torus);
+        let west = Region::HalfPlane { point: [-30000, 0], normal: [1.0, 0.0] };
+        assert!(west.contains(Coord(9, 9), &world));
+        let east = Region::HalfPlane { point: [30000, 0], normal: [-1.0, 0.0] };
+        assert!(east.contains(Coord(0, 0), &world));
+        let circle = Region::Circle { center: [-30000, -30000], radius: 30000.0 };
+        assert!(!circle.contains(Coord(9, 9), &world));
+        // -30000 is 0 on a torus 10 cells wide
+        assert!(circle.contains(Coord(9, 9), &torus));
+        let rectangle = Region::Rectangle { min: [-30000, -30000], max: [30000, 30000] };
+        assert!(rectangle.contains(Coord(9, 9), &world));
+    }
+}
+//</editor-fold>
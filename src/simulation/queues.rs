@@ -0,0 +1,175 @@
+use rand::Rng;
+use rand::seq::SliceRandom;
+use serde::{Serialize, Deserialize};
+use crate::population::individual::IndividualIndex;
+
+/// Order in which the queued moves are applied. Moves are applied one at a time, so when several
+/// individuals want to enter the same cell, the first one in this order gets it.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CollisionPolicy {
+    // Lowest index first
+    IndexOrder,
+    // A random order, drawn from the simulation's seeded generator
+    RandomOrder,
+    // Strongest urge to move first, ties broken by index
+    UrgeMagnitude,
+}
+
+/// Movement urges of every individual during a step, summed per individual. Indexed by the
+/// individual's index, so queueing never allocates.
+pub struct MoveQueue {
+    urges: Vec<(f32, f32)>,
+    queued: Vec<bool>,
+}
+
+impl MoveQueue {
+    pub fn new(population: IndividualIndex) -> MoveQueue {
+        // Index 0 is the placeholder individual
+        let size = population as usize + 1;
+        return MoveQueue { urges: vec![(0.0, 0.0); size], queued: vec![false; size] };
+    }
+
+    pub fn push(&mut self, index: IndividualIndex, urge: (f32, f32)) {
+        let sum = &mut self.urges[index as usize];
+        sum.0 += urge.0;
+        sum.1 += urge.1;
+        self.queued[index as usize] = true;
+    }
+
+    /// Sum of the urges queued for `index`, if there's any.
+    pub fn get(&self, index: IndividualIndex) -> Option<(f32, f32)> {
+        if !self.queued[index as usize] {
+            return None;
+        }
+        return Some(self.urges[index as usize]);
+    }
+
+    /// Calls `f` with the index and the summed urge of every individual with queued urges, in
+    /// index order, and empties the queue.
+    pub fn drain<F>(&mut self, mut f: F)
+        where F: FnMut(IndividualIndex, (f32, f32)) {
+        for (index, queued) in self.queued.iter_mut().enumerate() {
+            if *queued {
+                f(index as IndividualIndex, self.urges[index]);
+                self.urges[index] = (0.0, 0.0);
+                *queued = false;
+            }
+        }
+    }
+}
+
+/// Sorts `moves`, given in index order as (index, urge) pairs, in the order they must be applied.
+pub fn order_moves<R: Rng>(moves: &mut [(IndividualIndex, (f32, f32))], policy: CollisionPolicy, rng: &mut R) {
+    match policy {
+        CollisionPolicy::IndexOrder => {}
+        CollisionPolicy::RandomOrder => moves.shuffle(rng),
+        CollisionPolicy::UrgeMagnitude => {
+            // The sort is stable, so equal urges keep the index order
+            moves.sort_by(|a, b| {
+                let magnitude = |urge: (f32, f32)| urge.0.hypot(urge.1);
+                magnitude(b.1).total_cmp(&magnitude(a.1))
+            });
+        }
+    }
+}
+
+/// Set of individuals queued for something at the end of the step, indexed like the [`MoveQueue`].
+pub struct IndexQueue {
+    queued: Vec<bool>,
+}
+
+/// Individuals to be removed at the end of the step.
+pub type DeathQueue = IndexQueue;
+
+/// Individuals that want to have a child at the end of the step, in the continuous generation mode.
+pub type BirthQueue = IndexQueue;
+
+/// Individuals that want to eat the food of their cell at the end of the step.
+pub type EatQueue = IndexQueue;
+
+/// Individuals that want to kill the individual in front of them at the end of the step.
+pub type KillQueue = IndexQueue;
+
+impl IndexQueue {
+    pub fn new(population: IndividualIndex) -> IndexQueue {
+        return IndexQueue { queued: vec![false; population as usize + 1] };
+    }
+
+    pub fn push(&mut self, index: IndividualIndex) {
+        self.queued[index as usize] = true;
+    }
+
+    pub fn contains(&self, index: IndividualIndex) -> bool {
+        return self.queued[index as usize];
+    }
+
+    /// Calls `f` with every queued index, in increasing order, and empties the queue.
+    pub fn drain<F>(&mut self, mut f: F)
+        where F: FnMut(IndividualIndex) {
+        for (index, queued) in self.queued.iter_mut().enumerate() {
+            if *queued {
+                f(index as IndividualIndex);
+                *queued = false;
+            }
+        }
+    }
+}
+
+//<editor-fold desc="Unit tests">
+#[cfg(test)]
+mod test {
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use super::*;
+
+    #[test]
+    fn test_move_queue_sums_urges() {
+        let mut queue = MoveQueue::new(4);
+        queue.push(3, (0.5, 0.0));
+        queue.push(1, (0.0, -0.25));
+        queue.push(3, (0.25, 1.0));
+        assert_eq!(queue.get(2), None);
+
+        let mut drained = Vec::new();
+        queue.drain(|index, urge| drained.push((index, urge)));
+        assert_eq!(drained, vec![(1, (0.0, -0.25)), (3, (0.75, 1.0))]);
+        assert_eq!(queue.get(3), None);
+    }
+
+    #[test]
+    fn test_order_moves() {
+        let moves = vec![(1, (0.1, 0.0)), (2, (0.0, -0.9)), (3, (0.5, 0.5)), (4, (0.0, 0.1))];
+        let mut rng = StdRng::seed_from_u64(7);
+
+        let mut ordered = moves.clone();
+        order_moves(&mut ordered, CollisionPolicy::IndexOrder, &mut rng);
+        assert_eq!(ordered, moves);
+
+        order_moves(&mut ordered, CollisionPolicy::UrgeMagnitude, &mut rng);
+        let indices: Vec<IndividualIndex> = ordered.iter().map(|m| m.0).collect();
+        assert_eq!(indices, vec![2, 3, 1, 4]);
+
+        // The same seed gives the same order
+        let mut first = moves.clone();
+        let mut second = moves.clone();
+        order_moves(&mut first, CollisionPolicy::RandomOrder, &mut StdRng::seed_from_u64(3));
+        order_moves(&mut second, CollisionPolicy::RandomOrder, &mut StdRng::seed_from_u64(3));
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn test_death_queue_drains_in_index_order() {
+        let mut queue = DeathQueue::new(5);
+        queue.push(4);
+        queue.push(2);
+        queue.push(4);
+        assert!(queue.contains(2));
+
+        let mut drained = Vec::new();
+        queue.drain(|index| drained.push(index));
+        assert_eq!(drained, vec![2, 4]);
+        assert!(!queue.contains(4));
+    }
+}
+//</editor-fold>
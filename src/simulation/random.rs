@@ -0,0 +1,78 @@
+use std::cell::RefCell;
+use rand::{Error, RngCore, SeedableRng};
+use rand::rngs::StdRng;
+
+thread_local! {
+    // Source of every random decision of the simulation, so that a seed reproduces a whole run
+    static GENERATOR: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
+
+/// Restarts the simulation's generator of the current thread from `seed`.
+pub fn seed(seed: u64) {
+    GENERATOR.with(|generator| *generator.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
+/// Handle to the simulation's generator of the current thread. Use it like `rand::thread_rng()`.
+pub fn rng() -> SimulationRng {
+    return SimulationRng;
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SimulationRng;
+
+impl RngCore for SimulationRng {
+    fn next_u32(&mut self) -> u32 {
+        return GENERATOR.with(|generator| generator.borrow_mut().next_u32());
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        return GENERATOR.with(|generator| generator.borrow_mut().next_u64());
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        GENERATOR.with(|generator| generator.borrow_mut().fill_bytes(dest));
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        return GENERATOR.with(|generator| generator.borrow_mut().try_fill_bytes(dest));
+    }
+}
+
+//<editor-fold desc="Unit tests">
+#[cfg(test)]
+mod test {
+    use rand::Rng;
+    use crate::Parameters;
+    use crate::simulation::peeps::Peeps;
+    use super::*;
+
+    #[test]
+    fn test_seed_repeats_sequence() {
+        seed(42);
+        let first: Vec<u32> = (0..8).map(|_| rng().gen()).collect();
+        seed(42);
+        let second: Vec<u32> = (0..8).map(|_| rng().gen()).collect();
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn test_seeded_runs_are_reproducible() {
+        let mut parameters = Parameters::defaults();
+        parameters.size_x = 64;
+        parameters.size_y = 64;
+        parameters.population = 300;
+        parameters.seed = Some(5);
+
+        let mut locations = Vec::new();
+        for _ in 0..2 {
+            let mut peeps = Peeps::new(&parameters);
+            for step in 0..10 {
+                peeps.simulate_all(&parameters, step);
+            }
+            peeps.end_generation(0);
+            locations.push(peeps.get_population_locations());
+        }
+        assert_eq!(locations[0], locations[1]);
+    }
+}
+//</editor-fold>
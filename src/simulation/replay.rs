@@ -0,0 +1,308 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use crate::simulation::types::Coord;
+
+const MAGIC: &[u8; 4] = b"BSRP";
+const FORMAT_VERSION: u16 = 1;
+
+const TAG_BIRTH: u8 = 0;
+const TAG_MOVE: u8 = 1;
+const TAG_DEATH: u8 = 2;
+// Tag 3 is kept for signal emissions, which nothing emits yet
+const TAG_STEP_END: u8 = 4;
+const TAG_GENERATION_END: u8 = 5;
+
+/// Something that changed the world during a step. Together, the events of a run are enough to
+/// rebuild the location of every individual at every step, without evaluating any neural net.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayEvent {
+    Birth { index: u32, location: Coord },
+    Move { index: u32, location: Coord },
+    Death { index: u32 },
+    StepEnd { step: u32 },
+    GenerationEnd { generation: u32, survivors: Vec<u32> },
+}
+
+/// Events emitted by [`Peeps`](crate::simulation::peeps::Peeps) since they were last taken.
+/// Recording is a no-op unless the log is enabled.
+pub struct ReplayLog {
+    pub enabled: bool,
+    events: Vec<ReplayEvent>,
+}
+
+impl ReplayLog {
+    pub fn new(enabled: bool) -> ReplayLog {
+        return ReplayLog { enabled, events: Vec::new() };
+    }
+
+    #[inline]
+    pub fn record(&mut self, event: ReplayEvent) {
+        if self.enabled {
+            self.events.push(event);
+        }
+    }
+
+    pub fn take_events(&mut self) -> Vec<ReplayEvent> {
+        return std::mem::take(&mut self.events);
+    }
+}
+
+//<editor-fold desc="Binary encoding">
+// Events are stored as a tag byte followed by little-endian fields. Individual indices are written
+// as u32 so the format doesn't depend on the in-memory index type.
+fn write_coord<W: Write>(writer: &mut W, location: Coord) -> std::io::Result<()> {
+    writer.write_all(&location.0.to_le_bytes())?;
+    writer.write_all(&location.1.to_le_bytes())
+}
+
+fn read_u8<R: Read>(reader: &mut R) -> std::io::Result<u8> {
+    let mut buffer = [0u8; 1];
+    reader.read_exact(&mut buffer)?;
+    Ok(buffer[0])
+}
+
+fn read_u16<R: Read>(reader: &mut R) -> std::io::Result<u16> {
+    let mut buffer = [0u8; 2];
+    reader.read_exact(&mut buffer)?;
+    Ok(u16::from_le_bytes(buffer))
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> std::io::Result<u32> {
+    let mut buffer = [0u8; 4];
+    reader.read_exact(&mut buffer)?;
+    Ok(u32::from_le_bytes(buffer))
+}
+
+fn read_coord<R: Read>(reader: &mut R) -> std::io::Result<Coord> {
+    let x = read_u16(reader)? as i16;
+    let y = read_u16(reader)? as i16;
+    Ok(Coord(x, y))
+}
+
+impl ReplayEvent {
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        match self {
+            ReplayEvent::Birth { index, location } => {
+                writer.write_all(&[TAG_BIRTH])?;
+                writer.write_all(&index.to_le_bytes())?;
+                write_coord(writer, *location)
+            }
+            ReplayEvent::Move { index, location } => {
+                writer.write_all(&[TAG_MOVE])?;
+                writer.write_all(&index.to_le_bytes())?;
+                write_coord(writer, *location)
+            }
+            ReplayEvent::Death { index } => {
+                writer.write_all(&[TAG_DEATH])?;
+                writer.write_all(&index.to_le_bytes())
+            }
+            ReplayEvent::StepEnd { step } => {
+                writer.write_all(&[TAG_STEP_END])?;
+                writer.write_all(&step.to_le_bytes())
+            }
+            ReplayEvent::GenerationEnd { generation, survivors } => {
+                writer.write_all(&[TAG_GENERATION_END])?;
+                writer.write_all(&generation.to_le_bytes())?;
+                writer.write_all(&(survivors.len() as u32).to_le_bytes())?;
+                for survivor in survivors {
+                    writer.write_all(&survivor.to_le_bytes())?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    // Returns None at the end of the stream.
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Option<ReplayEvent>, Box<dyn Error>> {
+        let tag = match read_u8(reader) {
+            Ok(tag) => tag,
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        let event = match tag {
+            TAG_BIRTH => ReplayEvent::Birth { index: read_u32(reader)?, location: read_coord(reader)? },
+            TAG_MOVE => ReplayEvent::Move { index: read_u32(reader)?, location: read_coord(reader)? },
+            TAG_DEATH => ReplayEvent::Death { index: read_u32(reader)? },
+            TAG_STEP_END => ReplayEvent::StepEnd { step: read_u32(reader)? },
+            TAG_GENERATION_END => {
+                let generation = read_u32(reader)?;
+                let count = read_u32(reader)?;
+                let mut survivors = Vec::with_capacity(count as usize);
+                for _ in 0..count {
+                    survivors.push(read_u32(reader)?);
+                }
+                ReplayEvent::GenerationEnd { generation, survivors }
+            }
+            _ => return Err(format!("Unknown replay event tag {}", tag).into()),
+        };
+        return Ok(Some(event));
+    }
+}
+//</editor-fold>
+
+/// Streams the events of a run to a file, preceded by a small header with the world size.
+pub struct ReplayWriter {
+    writer: BufWriter<File>,
+}
+
+impl ReplayWriter {
+    pub fn create(path: &Path, width: u16, height: u16) -> Result<ReplayWriter, Box<dyn Error>> {
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        writer.write_all(&width.to_le_bytes())?;
+        writer.write_all(&height.to_le_bytes())?;
+        return Ok(ReplayWriter { writer });
+    }
+
+    pub fn write_events(&mut self, events: &[ReplayEvent]) -> Result<(), Box<dyn Error>> {
+        for event in events {
+            event.write_to(&mut self.writer)?;
+        }
+        return Ok(());
+    }
+
+    pub fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.writer.flush()?;
+        return Ok(());
+    }
+}
+
+/// A recorded run, loaded back from disk.
+pub struct Replay {
+    pub width: u16,
+    pub height: u16,
+    pub events: Vec<ReplayEvent>,
+}
+
+impl Replay {
+    pub fn read_from_file(path: &Path) -> Result<Replay, Box<dyn Error>> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err("Not a replay file".into());
+        }
+        let version = read_u16(&mut reader)?;
+        if version != FORMAT_VERSION {
+            return Err(format!("Unsupported replay version {}", version).into());
+        }
+        let width = read_u16(&mut reader)?;
+        let height = read_u16(&mut reader)?;
+
+        let mut events = Vec::new();
+        while let Some(event) = ReplayEvent::read_from(&mut reader)? {
+            events.push(event);
+        }
+        return Ok(Replay { width, height, events });
+    }
+
+    pub fn generations(&self) -> u32 {
+        return self.events.iter().filter(|e| matches!(e, ReplayEvent::GenerationEnd { .. })).count() as u32;
+    }
+
+    /// Rebuilds the locations of the living individuals at the end of every step of `generation`.
+    /// Generations are delimited by the `GenerationEnd` events, so the births that follow one of them
+    /// belong to the next generation.
+    pub fn generation_steps(&self, generation: u32) -> Vec<Vec<Coord>> {
+        let mut current_generation = 0;
+        let mut locations: HashMap<u32, Coord> = HashMap::new();
+        let mut steps = Vec::new();
+
+        for event in self.events.iter() {
+            if current_generation > generation {
+                break;
+            }
+
+            match event {
+                ReplayEvent::Birth { index, location } => {
+                    locations.insert(*index, *location);
+                }
+                ReplayEvent::Move { index, location } => {
+                    locations.insert(*index, *location);
+                }
+                ReplayEvent::Death { index } => {
+                    locations.remove(index);
+                }
+                ReplayEvent::StepEnd { .. } => {
+                    if current_generation == generation {
+                        steps.push(Replay::snapshot(&locations));
+                    }
+                }
+                ReplayEvent::GenerationEnd { .. } => {
+                    current_generation += 1;
+                    locations.clear();
+                }
+            }
+        }
+
+        return steps;
+    }
+
+    fn snapshot(locations: &HashMap<u32, Coord>) -> Vec<Coord> {
+        let mut indices: Vec<&u32> = locations.keys().collect();
+        indices.sort();
+        return indices.into_iter().map(|i| locations[i]).collect();
+    }
+}
+
+//<editor-fold desc="Unit tests">
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_event_round_trip() {
+        let events = vec![
+            ReplayEvent::Birth { index: 1, location: Coord(3, 4) },
+            ReplayEvent::Move { index: 1, location: Coord(-1, 5) },
+            ReplayEvent::StepEnd { step: 0 },
+            ReplayEvent::Death { index: 1 },
+            ReplayEvent::GenerationEnd { generation: 0, survivors: vec![2, 7] },
+        ];
+
+        let mut buffer = Vec::new();
+        for event in events.iter() {
+            event.write_to(&mut buffer).unwrap();
+        }
+
+        let mut reader = buffer.as_slice();
+        let mut decoded = Vec::new();
+        while let Some(event) = ReplayEvent::read_from(&mut reader).unwrap() {
+            decoded.push(event);
+        }
+        assert_eq!(decoded, events);
+    }
+
+    #[test]
+    fn test_generation_steps() {
+        let replay = Replay {
+            width: 10,
+            height: 10,
+            events: vec![
+                ReplayEvent::Birth { index: 1, location: Coord(0, 0) },
+                ReplayEvent::Birth { index: 2, location: Coord(5, 5) },
+                ReplayEvent::Move { index: 1, location: Coord(1, 0) },
+                ReplayEvent::StepEnd { step: 0 },
+                ReplayEvent::Death { index: 2 },
+                ReplayEvent::StepEnd { step: 1 },
+                ReplayEvent::GenerationEnd { generation: 0, survivors: vec![1] },
+                ReplayEvent::Birth { index: 1, location: Coord(9, 9) },
+                ReplayEvent::StepEnd { step: 2 },
+                ReplayEvent::GenerationEnd { generation: 1, survivors: vec![] },
+            ],
+        };
+
+        assert_eq!(replay.generations(), 2);
+        assert_eq!(replay.generation_steps(0), vec![vec![Coord(1, 0), Coord(5, 5)], vec![Coord(1, 0)]]);
+        assert_eq!(replay.generation_steps(1), vec![vec![Coord(9, 9)]]);
+    }
+}
+//</editor-fold>
@@ -14,6 +14,10 @@ impl Signals {
         return Signals { layers };
     }
 
+    pub fn num_layers(&self) -> usize {
+        self.layers.len()
+    }
+
     pub fn get(&self, layer: usize, location: Coord) -> u16 {
         self.layers[layer].at_coord(location)
     }
@@ -1,32 +1,250 @@
+use rand::Rng;
+use serde::{Serialize, Deserialize};
 use crate::Parameters;
 use crate::simulation::peeps::Peeps;
+use crate::population::genome::Genome;
+use crate::simulation::random;
+use crate::simulation::statistics::{GenerationStatistics, mean_mutation_rates, StatisticsHistory};
 
-pub struct Simulation<'a> {
+/// How the population is renewed.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum GenerationMode {
+    // Everyone lives `steps_per_generation` steps, then the survivors of the challenge breed the
+    // whole next generation
+    Discrete,
+    // Individuals are born through the reproduce action and die of age or starvation, so
+    // generations overlap. `steps_per_generation` only sets how often statistics are recorded.
+    Continuous,
+}
+
+/// Where the migrants of an island go.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MigrationTopology {
+    // Each island sends its migrants to the next one, and the last one to the first
+    Ring,
+    // Each migrant goes to a random other island
+    FullyConnected,
+}
+
+/// A population in its own world, with its own history.
+pub struct Island<'a> {
     pub peeps: Peeps<'a>,
+    pub statistics: StatisticsHistory,
+}
+
+pub struct Simulation<'a> {
+    // There's a single island unless `island_count` says otherwise
+    pub islands: Vec<Island<'a>>,
     pub parameters: &'a Parameters,
     pub simulation_step: u32,
+    pub generation: u32,
 }
 
 impl<'a> Simulation<'a>{
     pub fn initialize(parameters: &'a Parameters) -> Self {
+        let mut islands = Vec::with_capacity(parameters.island_count as usize);
+        for i in 0..parameters.island_count as usize {
+            let challenge = parameters.island_challenges.get(i).copied().unwrap_or(parameters.challenge);
+            // Only the first island is seeded, so that the others start from different populations
+            let mut peeps = if i == 0 { Peeps::new(parameters) } else { Peeps::new_island(parameters, challenge, parameters.island_map(i)) };
+            peeps.challenge = challenge;
+            // Replays only follow the first island
+            peeps.replay.enabled &= i == 0;
+            islands.push(Island { peeps, statistics: StatisticsHistory::new() });
+        }
         return Simulation {
-            peeps: Peeps::new(parameters),
+            islands,
             parameters,
             simulation_step: 0,
+            generation: 0,
         };
     }
 
+    // Runs a single step, ending the generation once `steps_per_generation` steps have been simulated.
     pub fn run_simulation_step(&mut self) {
-        self.peeps.simulate_all(&self.parameters, self.simulation_step);
+        for island in self.islands.iter_mut() {
+            island.peeps.simulate_all(self.parameters, self.simulation_step);
+        }
         self.simulation_step += 1;
+        if self.simulation_step.is_multiple_of(self.parameters.steps_per_generation as u32) {
+            self.end_generation();
+        }
     }
 
-    pub fn run_simulation(&mut self, generations: u32, steps: u32) {
+    pub fn run_simulation(&mut self, generations: u32) {
         for _ in 0..generations {
-            for _ in 0..steps {
+            let generation = self.generation;
+            while self.generation == generation {
                 self.run_simulation_step();
             }
-            self.peeps.end_generation();
         }
     }
-}
\ No newline at end of file
+
+    // In the continuous mode, this only ends the period the statistics are recorded for. Migrations
+    // happen between generations, once every `migration_interval`.
+    pub fn end_generation(&mut self) {
+        for island in self.islands.iter_mut() {
+            let statistics = Simulation::end_island_generation(&mut island.peeps, self.parameters, self.generation);
+            island.statistics.push(statistics);
+        }
+        self.generation += 1;
+
+        let interval = self.parameters.migration_interval;
+        if self.islands.len() > 1 && interval > 0 && self.generation.is_multiple_of(interval) {
+            self.migrate();
+        }
+    }
+
+    fn end_island_generation(peeps: &mut Peeps, parameters: &Parameters, generation: u32) -> GenerationStatistics {
+        // The population is described before end_generation replaces it
+        let mut statistics = GenerationStatistics::new(generation, &peeps.population, peeps.kills);
+        let (births, deaths_of_age, starvations) = (peeps.births, peeps.deaths_of_age, peeps.starvations);
+        let mutations = peeps.mutation_counts;
+        if parameters.self_adaptive_mutation_rates {
+            let genomes: Vec<&Genome> = peeps.population.iter().skip(1).filter(|i| i.alive).map(|i| &i.genome).collect();
+            statistics.mean_mutation_rates = Some(mean_mutation_rates(&genomes));
+        }
+        if parameters.track_lineage {
+            let living = peeps.living_ids();
+            let lineage = &peeps.lineage;
+            statistics.founder_lineages = Some(lineage.surviving_founder_lineages(&living) as u32);
+            statistics.generations_to_common_ancestor = lineage.time_to_most_recent_common_ancestor(&living, generation);
+        }
+
+        statistics.survivors = match parameters.generation_mode {
+            GenerationMode::Discrete => peeps.end_generation(generation),
+            GenerationMode::Continuous => peeps.end_period(generation),
+        };
+        statistics.mutations = match parameters.generation_mode {
+            GenerationMode::Discrete => peeps.mutation_counts,
+            // Children were bred during the period, before the counts were reset
+            GenerationMode::Continuous => mutations,
+        };
+        if parameters.generation_mode == GenerationMode::Continuous {
+            statistics.births = Some(births);
+            statistics.deaths_of_age = Some(deaths_of_age);
+            statistics.starvations = Some(starvations);
+        }
+        return statistics;
+    }
+
+    // Every island sends copies of `migrants` random genomes along the migration topology. All the
+    // migrants are chosen before any arrives, so that they don't travel twice.
+    pub fn migrate(&mut self) {
+        let p = self.parameters;
+        let count = self.islands.len();
+        let mut rng = random::rng();
+        let mut arrivals: Vec<Vec<Genome>> = vec![Vec::new(); count];
+        for (source, island) in self.islands.iter().enumerate() {
+            for genome in island.peeps.choose_emigrants(p.migrants) {
+                let destination = match p.migration_topology {
+                    MigrationTopology::Ring => (source + 1) % count,
+                    MigrationTopology::FullyConnected => (source + rng.gen_range(1..count)) % count,
+                };
+                arrivals[destination].push(genome);
+            }
+        }
+
+        for (island, genomes) in self.islands.iter_mut().zip(arrivals) {
+            island.peeps.immigrate(genomes, self.generation);
+        }
+    }
+}
+
+//<editor-fold desc="Unit tests">
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::population::genome::gene::Gene;
+    use crate::simulation::map::WorldMap;
+    use crate::simulation::peeps::survival_criteria::Challenges;
+    use crate::simulation::types::Coord;
+
+    fn island_parameters() -> Parameters {
+        let mut p = Parameters::defaults();
+        p.size_x = 32;
+        p.size_y = 32;
+        p.population = 20;
+        p.island_count = 3;
+        p.island_challenges = vec![Challenges::Circle, Challenges::Energy];
+        p.migrants = 3;
+        p.seed = Some(11);
+        return p;
+    }
+
+    #[test]
+    fn test_islands() {
+        let p = island_parameters();
+        let simulation = Simulation::initialize(&p);
+        assert_eq!(simulation.islands.len(), 3);
+        let challenges: Vec<Challenges> = simulation.islands.iter().map(|i| i.peeps.challenge).collect();
+        assert_eq!(challenges, vec![Challenges::Circle, Challenges::Energy, p.challenge]);
+        assert_ne!(simulation.islands[0].peeps.population[1].genome, simulation.islands[1].peeps.population[1].genome);
+    }
+
+    #[test]
+    fn test_statistics_describe_the_finished_generation() {
+        let mut p = island_parameters();
+        p.island_count = 1;
+        p.island_challenges.clear();
+        let mut simulation = Simulation::initialize(&p);
+        let marker = Genome::from(vec![Gene::new(true, 1, false, 2, 1234); 3]);
+        for individual in simulation.islands[0].peeps.population.iter_mut().skip(1) {
+            individual.genome = marker.clone();
+        }
+        simulation.end_generation();
+
+        let statistics = simulation.islands[0].statistics.last().unwrap();
+        assert_eq!(statistics.generation, 0);
+        assert_eq!(statistics.mean_genome_length, 3.0);
+        assert_eq!(statistics.diversity, 0.0);
+    }
+
+    #[test]
+    fn test_island_maps() {
+        // A 32x32 map with a single barrier on the north edge, x cells from the west edge
+        let map_with_barrier = |x: usize| {
+            let mut text = String::new();
+            for y in 0..32 {
+                let line: String = (0..32).map(|i| if y == 0 && i == x { '#' } else { '.' }).collect();
+                text.push_str(&line);
+                text.push('\n');
+            }
+            return WorldMap::from_text(&text).unwrap();
+        };
+        let mut p = island_parameters();
+        p.island_maps = vec![map_with_barrier(0), map_with_barrier(1)];
+        p.map = Some(map_with_barrier(2));
+        assert!(p.validate().is_ok());
+        let simulation = Simulation::initialize(&p);
+        let barriers: Vec<Vec<bool>> = simulation.islands.iter()
+            .map(|island| (0..3).map(|x| island.peeps.world.is_barrier_at(Coord(x, 31))).collect())
+            .collect();
+        assert_eq!(barriers, vec![vec![true, false, false], vec![false, true, false], vec![false, false, true]]);
+
+        p.island_maps.push(map_with_barrier(3));
+        p.island_maps.push(map_with_barrier(4));
+        assert!(p.validate().is_err());
+        p.island_maps = vec![WorldMap::from_text("..\n..\n").unwrap()];
+        assert!(p.validate().is_err());
+    }
+
+    #[test]
+    fn test_ring_migration() {
+        let p = island_parameters();
+        let mut simulation = Simulation::initialize(&p);
+        let marker = Genome::from(vec![Gene::new(true, 1, false, 2, 1234); 3]);
+        for individual in simulation.islands[0].peeps.population.iter_mut().skip(1) {
+            individual.genome = marker.clone();
+        }
+        simulation.migrate();
+
+        let marked = |island: &Island| island.peeps.population.iter().skip(1).filter(|i| i.genome == marker).count();
+        assert_eq!(marked(&simulation.islands[0]), 17);
+        assert_eq!(marked(&simulation.islands[1]), 3);
+        assert_eq!(marked(&simulation.islands[2]), 0);
+    }
+}
+//</editor-fold>
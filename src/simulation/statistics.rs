@@ -0,0 +1,169 @@
+use crate::population::genome::Genome;
+use crate::population::genome::mutations::{MutationCounts, MutationRates};
+use crate::population::genome::similarity::{genome_similarity, SimilarityMetric};
+use crate::population::individual::Individual;
+
+// Number of neighbouring genome pairs compared when estimating the genetic diversity.
+const DIVERSITY_SAMPLES: usize = 1000;
+
+/// Summary of a finished generation. Its population is described right before it's replaced, and
+/// the survivors and mutations are counted while it breeds the next one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GenerationStatistics {
+    pub generation: u32,
+    pub survivors: u32,
+    pub diversity: f32,
+    pub mean_genome_length: f32,
+    pub kills: u32,
+    // Mutations while breeding the next generation
+    pub mutations: MutationCounts,
+    // Only counted in the continuous generation mode
+    pub births: Option<u32>,
+    pub deaths_of_age: Option<u32>,
+    pub starvations: Option<u32>,
+    // Mean of the rates carried by the genomes, only computed with self-adaptive mutation rates
+    pub mean_mutation_rates: Option<MutationRates>,
+    // Only computed when lineages are tracked
+    pub founder_lineages: Option<u32>,
+    pub generations_to_common_ancestor: Option<u32>,
+}
+
+impl GenerationStatistics {
+    pub fn new(generation: u32, population: &[Individual], kills: u32) -> GenerationStatistics {
+        // In the continuous mode, the population has slots for the dead
+        let genomes: Vec<&Genome> = population.iter().skip(1).filter(|i| i.alive).map(|i| &i.genome).collect();
+        return GenerationStatistics {
+            generation,
+            survivors: 0,
+            diversity: genetic_diversity(&genomes),
+            mean_genome_length: mean_genome_length(&genomes),
+            kills,
+            mutations: MutationCounts::default(),
+            mean_mutation_rates: None,
+            births: None,
+            deaths_of_age: None,
+            starvations: None,
+            founder_lineages: None,
+            generations_to_common_ancestor: None,
+        };
+    }
+}
+
+/// Returns 1.0 for a population of completely different genomes and 0.0 when all of them are equal.
+/// Like biosim4, neighbouring pairs of genomes are compared instead of all the possible pairs.
+pub fn genetic_diversity(genomes: &[&Genome]) -> f32 {
+    if genomes.len() < 2 {
+        return 0.0;
+    }
+
+    let samples = usize::min(DIVERSITY_SAMPLES, genomes.len() - 1);
+    let mut similarity_sum = 0.0;
+    for i in 0..samples {
+        similarity_sum += genome_similarity(genomes[i], genomes[i + 1], SimilarityMetric::JaroWinkler);
+    }
+
+    return 1.0 - similarity_sum / samples as f32;
+}
+
+pub fn mean_genome_length(genomes: &[&Genome]) -> f32 {
+    if genomes.is_empty() {
+        return 0.0;
+    }
+
+    let total_genes: usize = genomes.iter().map(|g| g.len()).sum();
+    return total_genes as f32 / genomes.len() as f32;
+}
+
+pub fn mean_mutation_rates(genomes: &[&Genome]) -> MutationRates {
+    if genomes.is_empty() {
+        return MutationRates { point: 0.0, insertion_deletion: 0.0 };
+    }
+
+    let count = genomes.len() as f64;
+    return MutationRates {
+        point: genomes.iter().map(|g| g.mutation_rates.point).sum::<f64>() / count,
+        insertion_deletion: genomes.iter().map(|g| g.mutation_rates.insertion_deletion).sum::<f64>() / count,
+    };
+}
+
+/// Per-generation history kept by the [`Simulation`](crate::simulation::simulation::Simulation).
+#[derive(Default)]
+pub struct StatisticsHistory {
+    pub generations: Vec<GenerationStatistics>,
+}
+
+impl StatisticsHistory {
+    pub fn new() -> StatisticsHistory {
+        return StatisticsHistory { generations: Vec::new() };
+    }
+
+    pub fn push(&mut self, statistics: GenerationStatistics) {
+        self.generations.push(statistics);
+    }
+
+    pub fn last(&self) -> Option<&GenerationStatistics> {
+        return self.generations.last();
+    }
+
+    // The series are returned as (generation, value) points, ready to be plotted.
+    pub fn series<F>(&self, f: F) -> Vec<(f64, f64)>
+        where F: Fn(&GenerationStatistics) -> f64 {
+        return self.generations.iter().map(|s| (s.generation as f64, f(s))).collect();
+    }
+
+    pub fn survivors(&self) -> Vec<(f64, f64)> {
+        return self.series(|s| s.survivors as f64);
+    }
+
+    pub fn diversity(&self) -> Vec<(f64, f64)> {
+        return self.series(|s| s.diversity as f64);
+    }
+
+    pub fn mean_genome_length(&self) -> Vec<(f64, f64)> {
+        return self.series(|s| s.mean_genome_length as f64);
+    }
+
+    pub fn kills(&self) -> Vec<(f64, f64)> {
+        return self.series(|s| s.kills as f64);
+    }
+
+    // Empty unless self-adaptive mutation rates are enabled
+    pub fn mean_point_mutation_rate(&self) -> Vec<(f64, f64)> {
+        return self.generations.iter()
+            .filter_map(|s| s.mean_mutation_rates.map(|rates| (s.generation as f64, rates.point)))
+            .collect();
+    }
+}
+
+//<editor-fold desc="Unit tests">
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::population::genome::gene::Gene;
+
+    #[test]
+    fn test_diversity_of_equal_genomes() {
+        let genome = Genome::from(vec![Gene::new(true, 1, false, 2, 100); 4]);
+        let genomes = vec![&genome, &genome, &genome];
+        assert_eq!(genetic_diversity(&genomes), 0.0);
+        assert_eq!(mean_genome_length(&genomes), 4.0);
+    }
+
+    #[test]
+    fn test_diversity_of_small_populations() {
+        let genome = Genome::from(vec![Gene::empty()]);
+        assert_eq!(genetic_diversity(&[]), 0.0);
+        assert_eq!(genetic_diversity(&[&genome]), 0.0);
+        assert_eq!(mean_genome_length(&[]), 0.0);
+    }
+
+    #[test]
+    fn test_mean_mutation_rates() {
+        let mut low = Genome::from(vec![Gene::empty()]);
+        low.mutation_rates = MutationRates { point: 0.001, insertion_deletion: 0.0 };
+        let mut high = low.clone();
+        high.mutation_rates = MutationRates { point: 0.003, insertion_deletion: 0.5 };
+        assert_eq!(mean_mutation_rates(&[&low, &high]), MutationRates { point: 0.002, insertion_deletion: 0.25 });
+    }
+}
+//</editor-fold>
@@ -1,5 +1,6 @@
 use std::f32::consts::TAU;
 use rand::Rng;
+use crate::simulation::random;
 use Compass::{Center, East, North, NorthEast, NorthWest, South, SouthEast, SouthWest, West};
 
 //<editor-fold desc="Constants">
@@ -41,7 +42,7 @@ impl Dir {
     ///
     /// # Arguments
     /// * `steps` - How many steps to rotate, positive values represent rotations to the right, negative values represent
-    /// rotations to the left
+    ///   rotations to the left
     pub fn rotate(&self, steps: i8) -> Dir {
         let mut direction = self.0;
         let mut direction_index = direction as usize;
@@ -66,7 +67,7 @@ impl Dir {
     }
 
     pub fn random() -> Dir {
-        return Dir(COORD_DIR_CONVERSION[rand::thread_rng().gen_range(0..8) as usize]);
+        return Dir(COORD_DIR_CONVERSION[random::rng().gen_range(0..8) as usize]);
     }
 }
 
@@ -81,7 +82,7 @@ impl From<Coord> for Dir {
         let f_y = c.1 as f32;
         let mut angle = f_y.atan2(f_x);
         if angle < 0.0 {
-            angle = TAU + angle;
+            angle += TAU;
         }
 
         angle += TAU / 16.0;
@@ -130,7 +131,7 @@ impl Coord {
     }
 
     pub fn normalize(&self) -> Coord {
-        let dir : Dir = self.clone().into();
+        let dir : Dir = (*self).into();
         return dir.into()
     }
 
@@ -148,9 +149,9 @@ impl Coord {
         let dot = self.0 as f32 * other.0 as f32 + self.1 as f32 * other.1 as f32;
         let cos_angle = dot / (first_magnitude * second_magnitude);
         //Assert delta of result.
-        assert!(cos_angle >= -1.0001 && cos_angle <= 1.0001);
+        assert!((-1.0001..=1.0001).contains(&cos_angle));
         //Clip value
-        f32::min(1.0, f32::max(-1.0, cos_angle))
+        cos_angle.clamp(-1.0, 1.0)
     }
 
     pub fn ray_sameness_dir(&self, other: Dir) -> f32 {
@@ -1,26 +1,114 @@
 use rand::Rng;
-use crate::simulation::grid::Grid;
+use rand::seq::SliceRandom;
+use crate::population::individual::{IndividualIndex, MAX_POPULATION};
+use crate::simulation::food::Food;
+use crate::simulation::grid::{Grid, Neighborhood, Topology};
+use crate::simulation::map::WorldMap;
+use crate::simulation::random;
 use crate::simulation::types::Coord;
 
+// Side of the square tiles used by the occupancy index
+const TILE_SIZE: u16 = 8;
+
+/// Content of a world cell: nothing, a barrier, or the index of the individual standing on it.
+/// Index 0 is the placeholder individual's, which is never in the world, so it stands for an empty
+/// cell, and the largest index stands for a barrier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Cell(IndividualIndex);
+
+impl Cell {
+    pub const EMPTY: Cell = Cell(0);
+    pub const BARRIER: Cell = Cell(IndividualIndex::MAX);
+
+    pub fn occupant(index: IndividualIndex) -> Cell {
+        assert!(index != 0 && index <= MAX_POPULATION, "{} isn't the index of an individual in the world", index);
+        return Cell(index);
+    }
+
+    #[inline]
+    pub fn is_empty(self) -> bool {
+        return self == Cell::EMPTY;
+    }
+
+    #[inline]
+    pub fn is_barrier(self) -> bool {
+        return self == Cell::BARRIER;
+    }
+
+    #[inline]
+    pub fn is_occupied(self) -> bool {
+        return self != Cell::EMPTY && self != Cell::BARRIER;
+    }
+
+    /// Index of the individual in the cell, if there's one.
+    #[inline]
+    pub fn occupant_index(self) -> Option<IndividualIndex> {
+        if self.is_occupied() {
+            return Some(self.0);
+        }
+        return None;
+    }
+}
+
+/// The grid where the individuals live, plus a spatial index with the number of individuals in each
+/// `TILE_SIZE` x `TILE_SIZE` tile. The neighborhood sensors use the index to skip the empty tiles
+/// instead of reading every cell.
+///
+/// Cells must be written through [`World::set_at_coord`] and [`World::zero_fill`] to keep the
+/// index up to date.
 pub struct World {
-    grid: Grid,
+    grid: Grid<Cell>,
     barrier_locations: Vec<Coord>,
     barrier_centers: Vec<Coord>,
+    tiles_x: u16,
+    occupants_per_tile: Vec<u16>,
+    // Food layer, empty unless a food pattern is set
+    pub food: Food,
+    // Cells where new individuals are placed, anywhere when empty
+    spawn_zone: Vec<Coord>,
 }
 
-const BARRIER_CELL: u16 = 0xffff;
-
 impl World {
-    pub fn new(width: u16, height: u16) -> World {
+    pub fn new(width: u16, height: u16, topology: Topology) -> World {
+        let tiles_x = width.div_ceil(TILE_SIZE);
+        let tiles_y = height.div_ceil(TILE_SIZE);
+        let tiles = tiles_x as usize * tiles_y as usize;
         World {
-            grid: Grid::new(width, height),
+            grid: Grid::with_topology(width, height, topology),
             barrier_locations: Vec::new(),
             barrier_centers: Vec::new(),
+            tiles_x,
+            occupants_per_tile: vec![0; tiles],
+            food: Food::empty(width, height, topology),
+            spawn_zone: Vec::new(),
         }
     }
 
+    /// A world with the size, barriers and spawn zone of the map.
+    pub fn with_map(map: &WorldMap, topology: Topology) -> World {
+        let mut world = World::new(map.width, map.height, topology);
+        world.barrier_locations = map.barriers.clone();
+        world.spawn_zone = map.spawn_zone.clone();
+        world.zero_fill();
+        return world;
+    }
+
+    /// A random empty cell of the spawn zone if there's one, else of the whole world.
     pub fn find_random_empty_location(&self) -> Coord {
-        let mut rng = rand::thread_rng();
+        let mut rng = random::rng();
+        if !self.spawn_zone.is_empty() {
+            // Random picks are fast while the zone has room, the full scan only runs when it's crowded
+            for _ in 0..16 {
+                let location = self.spawn_zone[rng.gen_range(0..self.spawn_zone.len())];
+                if self.is_empty_at(location) {
+                    return location;
+                }
+            }
+            let empty: Vec<Coord> = self.spawn_zone.iter().copied().filter(|c| self.is_empty_at(*c)).collect();
+            if let Some(location) = empty.choose(&mut rng) {
+                return *location;
+            }
+        }
         let mut location = Coord(rng.gen_range(0..self.width as i16), rng.gen_range(0..self.height as i16));
         while !self.is_empty_at(location) {
             location = Coord(rng.gen_range(0..self.width as i16), rng.gen_range(0..self.height as i16));
@@ -28,32 +116,268 @@ impl World {
         return location;
     }
 
+    /// A random empty cell at most `radius` cells away from `location` on each axis, if there's any.
+    pub fn find_empty_location_near(&self, location: Coord, radius: i16) -> Option<Coord> {
+        let mut candidates = Vec::new();
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                if let Some(candidate) = self.resolve(location + Coord(dx, dy)) {
+                    if self.is_empty_at(candidate) {
+                        candidates.push(candidate);
+                    }
+                }
+            }
+        }
+        return candidates.choose(&mut random::rng()).copied();
+    }
+
     #[inline]
     pub fn is_barrier_at(&self, location: Coord) -> bool {
-        return self.grid.at_coord(location) == BARRIER_CELL;
+        return self.grid.at_coord(location).is_barrier();
     }
 
     #[inline]
     pub fn is_occupied_at(&self, location: Coord) -> bool {
-        return !(self.grid.is_empty_at(location) || self.is_barrier_at(location));
+        return self.grid.at_coord(location).is_occupied();
+    }
+
+    //<editor-fold desc="Occupancy index">
+    #[inline]
+    fn tile_of(&self, location: Coord) -> usize {
+        let (tile_x, tile_y) = (location.0 as u16 / TILE_SIZE, location.1 as u16 / TILE_SIZE);
+        return tile_y as usize * self.tiles_x as usize + tile_x as usize;
+    }
+
+    pub fn set_at_coord(&mut self, location: Coord, value: Cell) {
+        let tile = self.tile_of(location);
+        if self.grid.at_coord(location).is_occupied() {
+            self.occupants_per_tile[tile] -= 1;
+        }
+        if value.is_occupied() {
+            self.occupants_per_tile[tile] += 1;
+        }
+        self.grid.set_at_coord(location, value);
+    }
+
+    pub fn set_at(&mut self, x: u16, y: u16, value: Cell) {
+        self.set_at_coord(Coord(x as i16, y as i16), value);
+    }
+
+    // Empties the world, except for the barriers
+    pub fn zero_fill(&mut self) {
+        self.grid.zero_fill();
+        self.occupants_per_tile.fill(0);
+        for location in self.barrier_locations.iter() {
+            self.grid.set_at_coord(*location, Cell::BARRIER);
+        }
+    }
+
+    /// Number of individuals in the tile containing `location`.
+    #[inline]
+    pub fn occupants_in_tile(&self, location: Coord) -> u16 {
+        return self.occupants_per_tile[self.tile_of(location)];
     }
 
+    /// Calls `f` on every occupied cell of the neighborhood, in the same order as
+    /// [`Grid::apply_neighborhood_to_f`] would, but skips the tiles without occupants. Returns the
+    /// number of cells in the neighborhood, like [`Grid::neighborhood_size`].
+    pub fn apply_to_occupants_in_neighborhood<F>(&self, location: Coord, neighborhood: Neighborhood, mut f: F) -> u32
+        where F: FnMut(Coord) {
+        return self.apply_to_occupied_segments(location, neighborhood, |x, y, cells| {
+            for (offset, cell) in cells.iter().enumerate() {
+                if cell.is_occupied() {
+                    f(Coord(x + offset as i16, y));
+                }
+            }
+        });
+    }
+
+    /// Returns the number of occupied cells in the neighborhood and the number of cells in it.
+    pub fn count_occupants_in_neighborhood(&self, location: Coord, neighborhood: Neighborhood) -> (u32, u32) {
+        let mut occupied = 0;
+        let size = self.apply_to_occupied_segments(location, neighborhood, |_, _, cells| {
+            // Counting without branches is much faster than testing every cell
+            occupied += cells.iter().map(|cell| cell.is_occupied() as u32).sum::<u32>();
+        });
+        return (occupied, size);
+    }
+
+    // Walks the neighborhood row by row and calls `f` with the x and y of the first cell of each
+    // run of cells that shares a tile with some occupant, and the cells themselves. Returns the
+    // number of cells in the neighborhood.
+    fn apply_to_occupied_segments<F>(&self, location: Coord, neighborhood: Neighborhood, mut f: F) -> u32
+        where F: FnMut(i16, i16, &[Cell]) {
+        let width = self.width as i16;
+        let radius = neighborhood.radius;
+        let mut size = 0;
+        for dy in -radius..=radius {
+            let half_width = neighborhood.half_width(dy);
+            if half_width < 0 {
+                continue;
+            }
+            let y = match self.resolve(Coord(0, location.1 + dy)) {
+                Some(c) => c.1,
+                None => continue,
+            };
+            let row_start = y as usize * self.width as usize;
+            let row = &self.cells()[row_start..row_start + self.width as usize];
+            let tile_row = &self.occupants_per_tile[(y as u16 / TILE_SIZE) as usize * self.tiles_x as usize..];
+
+            let (mut left, mut right) = (location.0 - half_width, location.0 + half_width);
+            if self.topology == Topology::Bounded {
+                left = i16::max(left, 0);
+                right = i16::min(right, width - 1);
+            }
+            // Split the row where it wraps around the grid, then leave out the empty tiles
+            while left <= right {
+                let start = left.rem_euclid(width) as usize;
+                let end = usize::min(start + (right - left + 1) as usize, width as usize);
+                let mut run_start = start;
+                let mut x = start;
+                while x < end {
+                    let tile_end = usize::min((x / TILE_SIZE as usize + 1) * TILE_SIZE as usize, end);
+                    if tile_row[x / TILE_SIZE as usize] == 0 {
+                        if run_start < x {
+                            f(run_start as i16, y, &row[run_start..x]);
+                        }
+                        run_start = tile_end;
+                    }
+                    x = tile_end;
+                }
+                if run_start < end {
+                    f(run_start as i16, y, &row[run_start..end]);
+                }
+                size += (end - start) as u32;
+                left += (end - start) as i16;
+            }
+        }
+        return size;
+    }
+    //</editor-fold>
 
     //TODO: Implement the createBarrier in a better way
 }
 
 impl std::ops::Deref for World {
-    type Target = Grid;
+    type Target = Grid<Cell>;
 
     #[inline]
-    fn deref(&self) -> &Grid {
+    fn deref(&self) -> &Grid<Cell> {
         return &self.grid;
     }
 }
 
-impl std::ops::DerefMut for World {
-    #[inline]
-    fn deref_mut(&mut self) -> &mut Grid {
-        return &mut self.grid;
+//<editor-fold desc="Unit tests">
+#[cfg(test)]
+mod test {
+    use rand::Rng;
+    use crate::simulation::grid::NeighborhoodShape;
+    use super::*;
+
+    fn make_world(topology: Topology) -> World {
+        let mut world = World::new(37, 29, topology);
+        let mut rng = rand::thread_rng();
+        for i in 1..200 {
+            let location = world.find_random_empty_location();
+            world.set_at_coord(location, Cell::occupant(i));
+        }
+        for _ in 0..20 {
+            let location = Coord(rng.gen_range(0..37), rng.gen_range(0..29));
+            world.set_at_coord(location, Cell::BARRIER);
+        }
+        return world;
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_index_tracks_cells() {
+        let mut world = World::new(20, 20, Topology::Bounded);
+        world.set_at_coord(Coord(1, 1), Cell::occupant(5));
+        world.set_at_coord(Coord(2, 1), Cell::BARRIER);
+        assert_eq!(world.occupants_in_tile(Coord(7, 7)), 1);
+
+        world.set_at_coord(Coord(1, 1), Cell::EMPTY);
+        world.set_at_coord(Coord(2, 1), Cell::occupant(3));
+        assert_eq!(world.occupants_in_tile(Coord(0, 0)), 1);
+        // The last tile is clipped by the edge of the grid
+        world.set_at_coord(Coord(19, 19), Cell::occupant(4));
+        assert_eq!(world.occupants_in_tile(Coord(16, 16)), 1);
+
+        world.zero_fill();
+        assert_eq!(world.occupants_in_tile(Coord(0, 0)), 0);
+    }
+
+    #[test]
+    fn test_cell_kinds() {
+        // Indices beyond 16 bits are occupants like any other
+        let cell = Cell::occupant(300_000);
+        assert!(cell.is_occupied() && !cell.is_empty() && !cell.is_barrier());
+        assert_eq!(cell.occupant_index(), Some(300_000));
+        assert_eq!(Cell::occupant(MAX_POPULATION).occupant_index(), Some(MAX_POPULATION));
+        assert_eq!(Cell::BARRIER.occupant_index(), None);
+        assert_eq!(Cell::EMPTY.occupant_index(), None);
+        assert_eq!(Cell::default(), Cell::EMPTY);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_barrier_isnt_an_occupant() {
+        Cell::occupant(MAX_POPULATION + 1);
+    }
+
+    #[test]
+    fn test_find_empty_location_near() {
+        let mut world = World::new(10, 10, Topology::Bounded);
+        for location in [Coord(0, 1), Coord(1, 0), Coord(1, 1)] {
+            world.set_at_coord(location, Cell::occupant(1));
+        }
+        assert_eq!(world.find_empty_location_near(Coord(0, 0), 1), Some(Coord(0, 0)));
+        world.set_at_coord(Coord(0, 0), Cell::BARRIER);
+        assert_eq!(world.find_empty_location_near(Coord(0, 0), 1), None);
+
+        let location = world.find_empty_location_near(Coord(0, 0), 2).unwrap();
+        assert!(location.0 <= 2 && location.1 <= 2 && world.is_empty_at(location));
+    }
+
+    #[test]
+    fn test_world_from_map() {
+        let map = WorldMap::from_text("#...\n#SS.\n#...\n").unwrap();
+        let mut world = World::with_map(&map, Topology::Bounded);
+        assert!(world.is_barrier_at(Coord(0, 2)));
+        for i in 1..=2 {
+            let location = world.find_random_empty_location();
+            assert!(map.spawn_zone.contains(&location));
+            world.set_at_coord(location, Cell::occupant(i));
+        }
+        // Once the spawn zone is full, anywhere else will do
+        let location = world.find_random_empty_location();
+        assert!(location.0 > 0 && !map.spawn_zone.contains(&location));
+
+        world.zero_fill();
+        assert!(world.is_barrier_at(Coord(0, 0)));
+        assert!(world.is_empty_at(Coord(1, 1)));
+    }
+
+    #[test]
+    fn test_occupants_match_full_scan() {
+        for topology in [Topology::Bounded, Topology::Torus] {
+            let world = make_world(topology);
+            for shape in [NeighborhoodShape::VonNeumann, NeighborhoodShape::Moore, NeighborhoodShape::Euclidean] {
+                for location in [Coord(0, 0), Coord(18, 14), Coord(36, 28), Coord(9, 27)] {
+                    let neighborhood = Neighborhood::new(shape, 11);
+                    let mut expected = Vec::new();
+                    world.apply_neighborhood_to_f(location, neighborhood, |c| {
+                        if world.is_occupied_at(c) {
+                            expected.push(c);
+                        }
+                    });
+                    let mut found = Vec::new();
+                    let size = world.apply_to_occupants_in_neighborhood(location, neighborhood, |c| found.push(c));
+                    assert_eq!(found, expected);
+                    assert_eq!(size, world.neighborhood_size(location, neighborhood));
+                    assert_eq!(world.count_occupants_in_neighborhood(location, neighborhood), (found.len() as u32, size));
+                }
+            }
+        }
+    }
+}
+//</editor-fold>